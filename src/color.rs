@@ -0,0 +1,131 @@
+use clap::ValueEnum;
+use colored::Color;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal};
+use crate::todo::Priority;
+
+/// When output is colored.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// When stdout is a terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color, given the value of `NO_COLOR` and whether stdout is a terminal.
+    /// As https://no-color.org asks, an empty `NO_COLOR` counts as unset.
+    pub fn enabled(self, no_color: Option<OsString>, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => no_color.is_none_or(|no_color| no_color.is_empty()) && is_terminal,
+        }
+    }
+
+    /// Turns color on or off for everything printed from here on.
+    pub fn apply(self) {
+        let enabled = self.enabled(env::var_os("NO_COLOR"), io::stdout().is_terminal());
+
+        colored::control::set_override(enabled);
+    }
+}
+
+/// A color named the way `colored` names them, e.g. "red" or "bright blue".
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct ThemeColor {
+    name: String,
+    color: Color,
+}
+
+impl ThemeColor {
+    pub fn color(&self) -> Color {
+        self.color
+    }
+}
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<ThemeColor, String> {
+        match name.parse() {
+            Ok(color) => Ok(ThemeColor { name, color }),
+            Err(()) => Err(format!(
+                "unknown color `{}`, expected black, red, green, yellow, blue, magenta, cyan or white, optionally prefixed with `bright `",
+                name
+            )),
+        }
+    }
+}
+
+impl From<ThemeColor> for String {
+    fn from(color: ThemeColor) -> String {
+        color.name
+    }
+}
+
+fn theme_color(name: &str) -> ThemeColor {
+    ThemeColor::try_from(name.to_string()).expect("default theme colors are valid")
+}
+
+/// The colors todos are shown in. Done, overdue and blocked todos have colors of
+/// their own; open ones take the color of their priority.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub done: ThemeColor,
+    pub overdue: ThemeColor,
+    pub blocked: ThemeColor,
+    pub high: ThemeColor,
+    pub medium: ThemeColor,
+    pub low: ThemeColor,
+}
+
+impl Theme {
+    pub fn priority(&self, priority: Priority) -> Color {
+        match priority {
+            Priority::High => self.high.color(),
+            Priority::Medium => self.medium.color(),
+            Priority::Low => self.low.color(),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            done: theme_color("green"),
+            overdue: theme_color("red"),
+            blocked: theme_color("magenta"),
+            high: theme_color("red"),
+            medium: theme_color("yellow"),
+            low: theme_color("cyan"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enabled() {
+        assert!(ColorChoice::Auto.enabled(None, true));
+        assert!(ColorChoice::Auto.enabled(Some(OsString::new()), true));
+        assert!(!ColorChoice::Auto.enabled(Some(OsString::from("1")), true));
+        assert!(!ColorChoice::Auto.enabled(None, false));
+        assert!(ColorChoice::Always.enabled(Some(OsString::from("1")), false));
+        assert!(!ColorChoice::Never.enabled(None, true));
+    }
+
+    #[test]
+    fn test_theme_color_from_name() {
+        assert_eq!(ThemeColor::try_from("Bright Blue".to_string()).unwrap().color(), Color::BrightBlue);
+        assert!(ThemeColor::try_from("mauve".to_string()).unwrap_err().starts_with("unknown color `mauve`"));
+    }
+}
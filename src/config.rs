@@ -0,0 +1,516 @@
+use chrono::format::{Item, StrftimeItems};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, TableLike};
+use crate::color::Theme;
+use crate::due::DUE_FORMAT;
+use crate::error::NudgelystError;
+use crate::location::discover_local_todo_file;
+use crate::notifier::NotifierKind;
+use crate::sort::SortOrder;
+use crate::storage::Backend;
+use crate::subtasks::CompletePolicy;
+use crate::table::Column;
+
+/// Per-project settings, found next to a project's list the same way the list is.
+/// They take precedence over the user config file.
+pub const PROJECT_CONFIG_FILE: &str = "nudgelyst.toml";
+
+/// User configuration read from `$XDG_CONFIG_HOME/nudgelyst/config.toml`
+/// (falling back to `~/.config/nudgelyst/config.toml`).
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub storage: StorageConfig,
+    pub notify: NotifyConfig,
+    pub subtasks: SubtasksConfig,
+    pub table: TableConfig,
+    pub display: DisplayConfig,
+    pub theme: Theme,
+    /// Settings for particular todo lists, keyed by the list's path.
+    pub lists: BTreeMap<String, ListConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Where the todo list is stored. A leading `~/` is expanded to the home directory.
+    pub path: Option<PathBuf>,
+    /// Which storage engine to use; YAML when unset.
+    pub backend: Option<Backend>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// How nudges are sent; the terminal when empty.
+    pub via: Vec<NotifierKind>,
+    /// Where `webhook` nudges are POSTed.
+    pub webhook_url: Option<String>,
+    /// The shell command `command` nudges run.
+    pub command: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubtasksConfig {
+    /// What `todone` does with a todo that still has open subtasks.
+    pub on_complete: CompletePolicy,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TableConfig {
+    /// The columns `list` shows when not given `--columns`; all but notes when unset.
+    pub columns: Option<Vec<Column>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// How many of the newest todos `todo` lists after adding one.
+    pub recent: usize,
+    /// How many todos `list` and `next` show when not given `--limit`; all of them when unset.
+    pub limit: Option<usize>,
+    /// The order `list` and `next` show todos in when not given `--sort`.
+    pub sort: SortOrder,
+    /// How tables and `show` write dates, in strftime syntax.
+    #[serde(deserialize_with = "date_format")]
+    pub date_format: String,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> DisplayConfig {
+        DisplayConfig { recent: 5, limit: None, sort: SortOrder::default(), date_format: DUE_FORMAT.to_string() }
+    }
+}
+
+fn date_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let format = String::deserialize(deserializer)?;
+    if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+        return Err(D::Error::custom(format!("invalid date format `{}`", format)));
+    }
+
+    Ok(format)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListConfig {
+    /// Used instead of `[notify]` for nudges about this list.
+    pub notify: Option<NotifyConfig>,
+}
+
+impl Config {
+    /// Loads the user configuration with the nearest project's settings on top,
+    /// unless `global` is set. Returns the defaults if neither file exists.
+    pub fn load(global: bool) -> Result<Config, NudgelystError> {
+        let project = if global { None } else { project_config_file_path(&env::current_dir()?) };
+
+        Config::load_layered(config_file_path().as_deref(), project.as_deref())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Config, NudgelystError> {
+        Config::load_layered(Some(path), None)
+    }
+
+    /// Loads `user` and then `project` over it: a setting in `project` replaces the
+    /// same one in `user`, while tables are merged. Either file may be missing.
+    /// A relative `storage.path` in `project` is relative to the project.
+    pub fn load_layered(user: Option<&Path>, project: Option<&Path>) -> Result<Config, NudgelystError> {
+        let mut merged = toml::Table::new();
+        if let Some(user) = user {
+            merge(&mut merged, read_table(user)?);
+        }
+        if let Some(project) = project {
+            let mut table = read_table(project)?;
+            if let (Some(toml::Value::String(path)), Some(dir)) = (table.get_mut("storage").and_then(|storage| storage.get_mut("path")), project.parent()) {
+                if Path::new(path).is_relative() && !path.starts_with('~') {
+                    *path = dir.join(&*path).to_string_lossy().into_owned();
+                }
+            }
+            merge(&mut merged, table);
+        }
+
+        Config::deserialize(toml::Value::Table(merged)).map_err(|err| NudgelystError::InvalidInput(err.to_string()))
+    }
+
+    /// The value of the setting at `key`, e.g. `display.sort`, defaults included.
+    pub fn get(&self, key: &str) -> Result<toml::Value, NudgelystError> {
+        let mut value = toml::Value::try_from(self).map_err(|err| NudgelystError::Storage(err.to_string()))?;
+        for segment in split_key(key)? {
+            value = value
+                .as_table_mut()
+                .and_then(|table| table.remove(&segment))
+                .ok_or_else(|| NudgelystError::InvalidInput(format!("no value set for `{}`", key)))?;
+        }
+
+        Ok(value)
+    }
+
+    /// Every setting that has a value, as dotted keys sorted by key.
+    pub fn entries(&self) -> Result<Vec<(String, toml::Value)>, NudgelystError> {
+        let value = toml::Value::try_from(self).map_err(|err| NudgelystError::Storage(err.to_string()))?;
+        let mut entries = Vec::new();
+        flatten("", value, &mut entries);
+
+        Ok(entries)
+    }
+
+    /// The notification settings for the list at `todo_file`.
+    pub fn notify_for(&self, todo_file: &Path) -> &NotifyConfig {
+        self.lists
+            .iter()
+            .find(|(path, _)| same_file(&expand_home(Path::new(path)), todo_file))
+            .and_then(|(_, list)| list.notify.as_ref())
+            .unwrap_or(&self.notify)
+    }
+}
+
+/// Sets `key` to `value` in the config file at `path`, creating it if need be and
+/// keeping its comments. `value` is read as TOML if it can be, e.g. `10` or
+/// `["desktop"]`, and as a string otherwise. Nothing is written if the result
+/// isn't a valid config.
+pub fn set_in_file(path: &Path, key: &str, value: &str) -> Result<(), NudgelystError> {
+    let content = if path.exists() { fs::read_to_string(path)? } else { String::new() };
+    let mut document: DocumentMut = content.parse().map_err(|err: toml_edit::TomlError| NudgelystError::Parse {
+        file: path.to_path_buf(),
+        line: None,
+        message: err.message().to_string(),
+    })?;
+    let value = value.parse::<toml_edit::Value>().unwrap_or_else(|_| value.into());
+
+    let segments = split_key(key)?;
+    let (last, parents) = segments.split_last().expect("split_key never returns an empty key");
+    let mut table: &mut dyn TableLike = document.as_table_mut();
+    for segment in parents {
+        let mut implicit = toml_edit::Table::new();
+        implicit.set_implicit(true);
+        table = table
+            .entry(segment)
+            .or_insert(toml_edit::Item::Table(implicit))
+            .as_table_like_mut()
+            .ok_or_else(|| NudgelystError::InvalidInput(format!("`{}` isn't a table of settings", segment)))?;
+    }
+    table.insert(last, toml_edit::Item::Value(value));
+
+    let content = document.to_string();
+    if let Err(err) = toml::from_str::<Config>(&content) {
+        // the file is left alone, so the line the error is on would mean nothing
+        return Err(match NudgelystError::toml(path, &content, err) {
+            NudgelystError::Parse { message, .. } => NudgelystError::InvalidInput(message),
+            err => err,
+        });
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)?;
+
+    Ok(())
+}
+
+/// Splits a dotted key like `lists."~/work.yaml".notify` into its parts.
+fn split_key(key: &str) -> Result<Vec<String>, NudgelystError> {
+    let invalid = || NudgelystError::InvalidInput(format!("invalid key `{}`", key));
+    let mut segments = vec![String::new()];
+    let mut quoted = false;
+    for char in key.chars() {
+        match (char, quoted) {
+            ('"', _) => quoted = !quoted,
+            ('.', false) => segments.push(String::new()),
+            (char, _) => segments.last_mut().expect("segments starts non-empty").push(char),
+        }
+    }
+
+    if quoted || segments.iter().any(|segment| segment.is_empty()) {
+        return Err(invalid());
+    }
+
+    Ok(segments)
+}
+
+/// Writes a key segment the way `split_key` reads it back, quoted when it has to be.
+fn key_segment(segment: &str) -> String {
+    if !segment.is_empty() && segment.chars().all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-') {
+        segment.to_string()
+    } else {
+        format!("\"{}\"", segment)
+    }
+}
+
+fn flatten(prefix: &str, value: toml::Value, entries: &mut Vec<(String, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() { key_segment(&key) } else { format!("{}.{}", prefix, key_segment(&key)) };
+                flatten(&key, value, entries);
+            }
+        },
+        value => entries.push((prefix.to_string(), value)),
+    }
+}
+
+fn read_table(path: &Path) -> Result<toml::Table, NudgelystError> {
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+
+    let content = fs::read_to_string(path)?;
+    // parsed as a whole config first, so mistakes are reported against this file
+    toml::from_str::<Config>(&content).map_err(|err| NudgelystError::toml(path, &content, err))?;
+    toml::from_str(&content).map_err(|err| NudgelystError::toml(path, &content, err))
+}
+
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => merge(base, overrides),
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// The location of the user config file, if a config directory can be determined.
+pub fn config_file_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("nudgelyst").join("config.toml"))
+}
+
+/// The nearest project config file at or above `start`, if there is one.
+pub fn project_config_file_path(start: &Path) -> Option<PathBuf> {
+    discover_local_todo_file(start, PROJECT_CONFIG_FILE)
+}
+
+/// `$XDG_CONFIG_HOME`, or `~/.config` when it is unset.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_DATA_HOME`, or `~/.local/share` when it is unset.
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Expands a leading `~/` to the user's home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn xdg_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+    // the XDG spec says relative paths in these variables should be ignored
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(home_fallback)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_from_missing_file_is_default() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+
+        // Act
+        let result = Config::load_from(&dir.path().join("config.toml"));
+
+        // Assert
+        match result {
+            Ok(config) => {
+                assert_eq!(config, Config::default());
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_from_storage_path() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, "[storage]\npath = \"/tmp/todos.db\"\nbackend = \"sqlite\"\n").unwrap();
+
+        // Act
+        let result = Config::load_from(&config_path);
+
+        // Assert
+        match result {
+            Ok(config) => {
+                assert_eq!(config.storage.path, Some(PathBuf::from("/tmp/todos.db")));
+                assert_eq!(config.storage.backend, Some(Backend::Sqlite));
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_from_invalid_file() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, "[storage\n").unwrap();
+
+        // Act
+        let result = Config::load_from(&config_path);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Parse { line: Some(1), .. })));
+    }
+
+    #[test]
+    fn test_notify_for_list_overrides_default() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        let work_list = dir.path().join("work.yaml");
+        fs::write(&config_path, format!(
+            "[notify]\nvia = [\"terminal\"]\n\n[lists.\"{}\".notify]\nvia = [\"webhook\"]\nwebhook_url = \"http://localhost/nudge\"\n",
+            work_list.display()
+        )).unwrap();
+
+        // Act
+        let result = Config::load_from(&config_path);
+
+        // Assert
+        match result {
+            Ok(config) => {
+                assert_eq!(config.notify_for(&work_list).via, vec![NotifierKind::Webhook]);
+                assert_eq!(config.notify_for(&work_list).webhook_url.as_deref(), Some("http://localhost/nudge"));
+                assert_eq!(config.notify_for(&dir.path().join("home.yaml")).via, vec![NotifierKind::Terminal]);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_layered_project_overrides_user() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let user_path = dir.path().join("config.toml");
+        let project_path = dir.path().join("project").join(PROJECT_CONFIG_FILE);
+        fs::create_dir(dir.path().join("project")).unwrap();
+        fs::write(&user_path, "[display]\nrecent = 3\nsort = \"due\"\n\n[theme]\ndone = \"blue\"\n").unwrap();
+        fs::write(&project_path, "[storage]\npath = \"todos.db\"\n\n[display]\nrecent = 10\n").unwrap();
+
+        // Act
+        let result = Config::load_layered(Some(&user_path), Some(&project_path));
+
+        // Assert
+        match result {
+            Ok(config) => {
+                assert_eq!(config.display.recent, 10);
+                assert_eq!(config.display.sort, SortOrder::Due);
+                assert_eq!(config.theme.done.color(), colored::Color::Blue);
+                assert_eq!(config.storage.path, Some(dir.path().join("project").join("todos.db")));
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_from_unknown_key() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, "[display]\nrecnet = 3\n").unwrap();
+
+        // Act
+        let result = Config::load_from(&config_path);
+
+        // Assert
+        match result {
+            Err(NudgelystError::Parse { line, message, .. }) => {
+                assert_eq!(line, Some(2));
+                assert!(message.starts_with("display.recnet: unknown field `recnet`"), "{}", message);
+            }
+            other => {
+                panic!("Unexpected result: {:?}", other);
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_in_file_keeps_comments() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, "# mine\n[display]\nrecent = 3\n").unwrap();
+
+        // Act
+        let result = set_in_file(&config_path, "display.limit", "20")
+            .and_then(|()| set_in_file(&config_path, "theme.done", "bright blue"))
+            .and_then(|()| set_in_file(&config_path, "lists.\"~/work.yaml\".notify.via", "[\"desktop\"]"));
+
+        // Assert
+        assert!(result.is_ok(), "{:?}", result);
+        let content = fs::read_to_string(&config_path).unwrap();
+        assert!(content.starts_with("# mine\n[display]\nrecent = 3\nlimit = 20\n"), "{}", content);
+        let config = Config::load_from(&config_path).unwrap();
+        assert_eq!(config.get("display.limit").unwrap(), toml::Value::Integer(20));
+        assert_eq!(config.get("theme.done").unwrap(), toml::Value::String("bright blue".to_string()));
+        assert_eq!(config.lists["~/work.yaml"].notify.as_ref().unwrap().via, vec![NotifierKind::Desktop]);
+    }
+
+    #[test]
+    fn test_set_in_file_rejects_invalid_settings() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+
+        // Act
+        let bad_value = set_in_file(&config_path, "display.date_format", "%Q");
+        let bad_key = set_in_file(&config_path, "display.colour", "red");
+
+        // Assert
+        match bad_value {
+            Err(NudgelystError::InvalidInput(message)) => {
+                assert_eq!(message, "display.date_format: invalid date format `%Q`");
+            }
+            other => {
+                panic!("Unexpected result: {:?}", other);
+            }
+        }
+        assert!(matches!(bad_key, Err(NudgelystError::InvalidInput(_))));
+        assert!(!config_path.exists());
+    }
+
+    #[test]
+    fn test_entries_include_defaults() {
+        // Act
+        let entries = Config::default().entries().unwrap();
+
+        // Assert
+        assert!(entries.contains(&("display.recent".to_string(), toml::Value::Integer(5))));
+        assert!(entries.contains(&("theme.high".to_string(), toml::Value::String("red".to_string()))));
+        assert!(!entries.iter().any(|(key, _)| key == "storage.path"));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::error::NudgelystError;
+use crate::todo::{Todo, TodoRepository};
+
+/// Makes `todo` depend on the todos with the ids in `on`, which must exist and not
+/// depend on `todo` themselves, directly or otherwise.
+pub fn add_dependencies(repo: &dyn TodoRepository, todo: Todo, on: &[usize]) -> Result<(), NudgelystError> {
+    let mut depends_on = todo.depends_on.clone();
+    for id in on {
+        if !depends_on.contains(id) {
+            depends_on.push(*id);
+        }
+    }
+
+    let todo = Todo { depends_on, ..todo };
+    check_dependencies(repo, &todo)?;
+    repo.update_todo(todo)
+}
+
+/// Stops `todo` depending on the todos with the ids in `on`.
+pub fn remove_dependencies(repo: &dyn TodoRepository, todo: Todo, on: &[usize]) -> Result<(), NudgelystError> {
+    let depends_on = todo.depends_on.iter().copied().filter(|id| !on.contains(id)).collect();
+
+    repo.update_todo(Todo { depends_on, ..todo })
+}
+
+/// Checks that everything `todo` depends on exists and that none of it leads back to `todo`.
+pub fn check_dependencies(repo: &dyn TodoRepository, todo: &Todo) -> Result<(), NudgelystError> {
+    let mut todos = repo.get_todos(None)?;
+    todos.retain(|existing| existing.id != todo.id);
+    todos.push(todo.clone());
+
+    for id in &todo.depends_on {
+        if *id == todo.id {
+            return Err(NudgelystError::InvalidInput(format!("todo {} can't depend on itself", todo.id)));
+        }
+        if !todos.iter().any(|existing| existing.id == *id) {
+            return Err(NudgelystError::not_found_id(*id));
+        }
+        if let Some(path) = dependency_path(&todos, *id, todo.id) {
+            let cycle: Vec<String> = std::iter::once(todo.id).chain(path).map(|id| id.to_string()).collect();
+            return Err(NudgelystError::InvalidInput(format!(
+                "todo {} can't depend on {}, that would make a cycle: {}",
+                todo.id,
+                id,
+                cycle.join(" -> ")
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// The incomplete todos among `todos` that `todo` is waiting on.
+pub fn blockers<'a>(todo: &Todo, todos: &'a [Todo]) -> Vec<&'a Todo> {
+    todos
+        .iter()
+        .filter(|other| !other.completed && todo.depends_on.contains(&other.id))
+        .collect()
+}
+
+/// The incomplete todos that aren't waiting on any other incomplete todo.
+pub fn actionable(todos: Vec<Todo>) -> Vec<Todo> {
+    let open: HashSet<usize> = todos.iter().filter(|todo| !todo.completed).map(|todo| todo.id).collect();
+
+    todos
+        .into_iter()
+        .filter(|todo| !todo.completed && !todo.depends_on.iter().any(|id| open.contains(id)))
+        .collect()
+}
+
+/// The chain of dependencies leading from `from` to `to`, both included, if there is one.
+fn dependency_path(todos: &[Todo], from: usize, to: usize) -> Option<Vec<usize>> {
+    let depends_on: HashMap<usize, &Vec<usize>> = todos.iter().map(|todo| (todo.id, &todo.depends_on)).collect();
+    let mut came_from: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    let mut seen = HashSet::from([from]);
+
+    while let Some(id) = queue.pop_front() {
+        if id == to {
+            let mut path = vec![to];
+            while let Some(previous) = came_from.get(path.last().unwrap()) {
+                path.push(*previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in depends_on.get(&id).into_iter().flat_map(|ids| ids.iter()) {
+            if seen.insert(*next) {
+                came_from.insert(*next, id);
+                queue.push_back(*next);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::in_memory_todo_repository::InMemoryTodoRepository;
+
+    /// 3 depends on 2, which depends on 1; 4 stands alone.
+    fn repo() -> InMemoryTodoRepository {
+        InMemoryTodoRepository::with_todos(vec![
+            Todo::new(0, "Write design doc".to_string()),
+            Todo { depends_on: vec![1], ..Todo::new(0, "Get design reviewed".to_string()) },
+            Todo { depends_on: vec![2], ..Todo::new(0, "Build it".to_string()) },
+            Todo::new(0, "Buy milk".to_string()),
+        ]).unwrap()
+    }
+
+    fn ids(todos: &[Todo]) -> Vec<usize> {
+        todos.iter().map(|todo| todo.id).collect()
+    }
+
+    #[test]
+    fn test_add_dependencies() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = add_dependencies(&repo, repo.get_todo(3).unwrap(), &[4, 2]);
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(repo.get_todo(3).unwrap().depends_on, vec![2, 4]);
+    }
+
+    #[test]
+    fn test_add_dependencies_rejects_cycles() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = add_dependencies(&repo, repo.get_todo(1).unwrap(), &[3]);
+
+        // Assert
+        match result {
+            Err(NudgelystError::InvalidInput(message)) => {
+                assert!(message.ends_with("1 -> 3 -> 2 -> 1"), "{}", message);
+            }
+            other => {
+                panic!("Unexpected result: {:?}", other);
+            }
+        }
+        assert!(repo.get_todo(1).unwrap().depends_on.is_empty());
+    }
+
+    #[test]
+    fn test_add_dependencies_rejects_self_and_missing_todos() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let on_itself = add_dependencies(&repo, repo.get_todo(4).unwrap(), &[4]);
+        let on_missing = add_dependencies(&repo, repo.get_todo(4).unwrap(), &[9]);
+
+        // Assert
+        assert!(matches!(on_itself, Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(on_missing, Err(NudgelystError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_remove_dependencies() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = remove_dependencies(&repo, repo.get_todo(3).unwrap(), &[2]);
+
+        // Assert
+        assert!(result.is_ok());
+        assert!(repo.get_todo(3).unwrap().depends_on.is_empty());
+    }
+
+    #[test]
+    fn test_blockers_and_actionable() {
+        // Arrange
+        let repo = repo();
+        let done = Todo { completed: true, ..repo.get_todo(1).unwrap() };
+        repo.update_todo(done).unwrap();
+        let todos = repo.get_todos(None).unwrap();
+
+        // Act
+        let blocking_3: Vec<usize> = blockers(&todos[2], &todos).iter().map(|todo| todo.id).collect();
+        let actionable = actionable(todos.clone());
+
+        // Assert
+        assert_eq!(blocking_3, vec![2]);
+        assert!(blockers(&todos[1], &todos).is_empty());
+        assert_eq!(ids(&actionable), vec![2, 4]);
+    }
+}
@@ -0,0 +1,361 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use crate::error::NudgelystError;
+
+/// How due dates are shown to people.
+pub const DUE_FORMAT: &str = "%a %b %-d %H:%M";
+
+/// Parses a due date the way people write them, relative to `now`:
+///
+/// - `today`, `tomorrow`, a weekday (`fri`, `friday`, `next fri`)
+/// - `in 3 days`, `in 2 weeks`, `in 4 hours`, `in 30 minutes`
+/// - a time of day on its own (`5pm`, `17:30`, `noon`)
+/// - ISO dates and datetimes (`2024-05-01`, `2024-05-01 17:00`, RFC 3339)
+///
+/// Days can be followed by a time (`tomorrow 5pm`, `fri at 9:30am`). A day
+/// without a time is due at the end of that day. A weekday always means the
+/// next one after today, and a time on its own means the next time the clock
+/// shows it.
+pub fn parse_due(input: &str, now: DateTime<Local>) -> Result<DateTime<Local>, NudgelystError> {
+    let input = input.trim().to_lowercase();
+
+    if let Some(due) = parse_iso(&input) {
+        return due.ok_or_else(|| invalid_due(&input));
+    }
+
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let due = match words.as_slice() {
+        ["in", amount, unit, time @ ..] => parse_offset(amount, unit, time, now),
+        [day, time @ ..] => match parse_day(day, now.date_naive()) {
+            Some(date) => at_time(date, time),
+            None => parse_time_only(&words, now),
+        },
+        [] => None,
+    };
+
+    due.ok_or_else(|| invalid_due(&input))
+}
+
+/// Parses how long before a todo is due to nudge about it, e.g. `1h`,
+/// `2 days` or `1h 30m`, into whole minutes.
+pub fn parse_reminder_offset(input: &str) -> Result<u64, NudgelystError> {
+    let compact: String = input.split_whitespace().collect();
+
+    let minutes = humantime::parse_duration(&compact)
+        .map(|offset| offset.as_secs() / 60)
+        .map_err(|err| NudgelystError::InvalidInput(format!("couldn't understand the reminder '{}': {}", input, err)))?;
+    if i64::try_from(minutes).ok().and_then(Duration::try_minutes).is_none() {
+        return Err(NudgelystError::InvalidInput(format!("the reminder '{}' is too far ahead", input)));
+    }
+
+    Ok(minutes)
+}
+
+/// Parses a time in the past into the span it names, start included and end
+/// excluded, for filtering on when things happened:
+///
+/// - `today`, `yesterday`, a weekday (`mon`, `last mon`) or an ISO date name the whole day
+/// - `3 days ago`, `2 hours ago` and ISO datetimes name a single moment, which starts
+///   and ends the span
+///
+/// A weekday means the most recent one, which is today on that day.
+pub fn parse_period(input: &str, now: DateTime<Local>) -> Result<(DateTime<Local>, DateTime<Local>), NudgelystError> {
+    let input = input.trim().to_lowercase();
+    let today = now.date_naive();
+    let words: Vec<&str> = input.split_whitespace().collect();
+
+    let day = match words.as_slice() {
+        ["today"] => Some(today),
+        ["yesterday"] => today.pred_opt(),
+        ["last", day] => parse_past_weekday(day, today),
+        [day] => parse_past_weekday(day, today).or_else(|| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()),
+        _ => None,
+    };
+    let period = match day {
+        Some(day) => day
+            .succ_opt()
+            .and_then(|next_day| local(day.and_time(NaiveTime::MIN)).zip(local(next_day.and_time(NaiveTime::MIN)))),
+        None => match words.as_slice() {
+            [amount, unit, "ago"] => parse_ago(amount, unit, now).map(|moment| (moment, moment)),
+            _ => parse_iso(&input).flatten().map(|moment| (moment, moment)),
+        },
+    };
+
+    period.ok_or_else(|| NudgelystError::InvalidInput(format!(
+        "couldn't understand the time '{}'; try something like 'yesterday', 'mon', '3 days ago' or '2024-05-01'",
+        input
+    )))
+}
+
+/// The last moment of `date`, which is when a todo due that day falls due.
+pub fn end_of_day(date: NaiveDate) -> Option<DateTime<Local>> {
+    at_time(date, &[])
+}
+
+fn invalid_due(input: &str) -> NudgelystError {
+    NudgelystError::InvalidInput(format!(
+        "couldn't understand the due date '{}'; try something like 'tomorrow 5pm', 'fri', 'in 3 days' or '2024-05-01'",
+        input
+    ))
+}
+
+/// `Some(None)` means the input looked like an ISO date but wasn't a valid one.
+fn parse_iso(input: &str) -> Option<Option<DateTime<Local>>> {
+    if let Ok(due) = DateTime::parse_from_rfc3339(input) {
+        return Some(Some(due.with_timezone(&Local)));
+    }
+
+    for format in ["%Y-%m-%dt%H:%M:%S", "%Y-%m-%dt%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(due) = NaiveDateTime::parse_from_str(input, format) {
+            return Some(local(due));
+        }
+    }
+
+    let mut words = input.split_whitespace();
+    let date = NaiveDate::parse_from_str(words.next()?, "%Y-%m-%d").ok()?;
+    let time: Vec<&str> = words.collect();
+
+    Some(at_time(date, &time))
+}
+
+fn parse_offset(amount: &str, unit: &str, time: &[&str], now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let amount: i64 = amount.parse().ok()?;
+
+    match unit.trim_end_matches('s') {
+        "min" | "minute" if time.is_empty() => now.checked_add_signed(Duration::try_minutes(amount)?),
+        "hour" | "hr" if time.is_empty() => now.checked_add_signed(Duration::try_hours(amount)?),
+        "day" => offset_days(now, amount, time),
+        "week" | "wk" => offset_days(now, amount.checked_mul(7)?, time),
+        _ => None,
+    }
+}
+
+/// Moves `now` on by whole days, keeping the time of day unless one is given.
+fn offset_days(now: DateTime<Local>, days: i64, time: &[&str]) -> Option<DateTime<Local>> {
+    let date = now.date_naive().checked_add_signed(Duration::try_days(days)?)?;
+
+    if time.is_empty() {
+        local(date.and_time(now.time()))
+    } else {
+        at_time(date, time)
+    }
+}
+
+fn parse_day(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match word {
+        "today" => Some(today),
+        "tomorrow" | "tmrw" => today.succ_opt(),
+        "next" => None,
+        _ => {
+            let weekday: Weekday = word.parse().ok()?;
+            let days_ahead = (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            // the same weekday as today means a week from now
+            let days_ahead = if days_ahead == 0 { 7 } else { days_ahead };
+
+            Some(today + Duration::days(days_ahead.into()))
+        }
+    }
+}
+
+fn parse_past_weekday(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let weekday: Weekday = word.parse().ok()?;
+    let days_back = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+
+    Some(today - Duration::days(days_back.into()))
+}
+
+fn parse_ago(amount: &str, unit: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let amount: i64 = amount.parse().ok()?;
+
+    let ago = match unit.trim_end_matches('s') {
+        "min" | "minute" => Duration::try_minutes(amount),
+        "hour" | "hr" => Duration::try_hours(amount),
+        "day" => Duration::try_days(amount),
+        "week" | "wk" => Duration::try_weeks(amount),
+        _ => None,
+    };
+
+    now.checked_sub_signed(ago?)
+}
+
+/// Handles a bare time, plus `next <weekday>` which reads the same as the weekday alone.
+fn parse_time_only(words: &[&str], now: DateTime<Local>) -> Option<DateTime<Local>> {
+    if let ["next", day, time @ ..] = words {
+        return at_time(parse_day(day, now.date_naive())?, time);
+    }
+
+    let time = parse_time(words)?;
+    let today = local(now.date_naive().and_time(time))?;
+    if today > now {
+        Some(today)
+    } else {
+        local(now.date_naive().succ_opt()?.and_time(time))
+    }
+}
+
+/// Puts a date at the given time of day, or at the end of the day when there isn't one.
+fn at_time(date: NaiveDate, time: &[&str]) -> Option<DateTime<Local>> {
+    let time = if time.is_empty() {
+        NaiveTime::from_hms_opt(23, 59, 59)?
+    } else {
+        parse_time(time)?
+    };
+
+    local(date.and_time(time))
+}
+
+/// `5pm`, `5 pm`, `5:30am`, `17:30`, `noon`, optionally after an `at`.
+fn parse_time(words: &[&str]) -> Option<NaiveTime> {
+    let words = match words {
+        ["at", rest @ ..] => rest,
+        _ => words,
+    };
+    let time = words.concat();
+
+    if time == "noon" {
+        return NaiveTime::from_hms_opt(12, 0, 0);
+    }
+
+    let (clock, offset) = if let Some(clock) = time.strip_suffix("am") {
+        (clock, Some(0))
+    } else if let Some(clock) = time.strip_suffix("pm") {
+        (clock, Some(12))
+    } else {
+        (time.as_str(), None)
+    };
+
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+        // a bare number is only a time with am/pm after it
+        None if offset.is_some() => (clock.parse::<u32>().ok()?, 0),
+        None => return None,
+    };
+
+    let hour = match offset {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None => hour,
+    };
+
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// Places a wall-clock time in the local time zone. Times skipped by a DST change don't exist.
+fn local(due: NaiveDateTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&due).earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a Wednesday
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap()
+    }
+
+    fn assert_due(input: &str, expected: &str) {
+        match parse_due(input, now()) {
+            Ok(due) => {
+                assert_eq!(due.naive_local().format("%Y-%m-%d %H:%M").to_string(), expected, "parsing '{}'", input);
+            }
+            Err(err) => {
+                panic!("Unexpected error for '{}': {:?}", input, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_days() {
+        assert_due("today", "2024-05-01 23:59");
+        assert_due("tomorrow", "2024-05-02 23:59");
+        assert_due("Tomorrow 5pm", "2024-05-02 17:00");
+        assert_due("tomorrow at 9:30am", "2024-05-02 09:30");
+    }
+
+    #[test]
+    fn test_parse_weekdays() {
+        assert_due("fri", "2024-05-03 23:59");
+        assert_due("friday 5 pm", "2024-05-03 17:00");
+        assert_due("next mon", "2024-05-06 23:59");
+        assert_due("wed", "2024-05-08 23:59");
+    }
+
+    #[test]
+    fn test_parse_offsets() {
+        assert_due("in 3 days", "2024-05-04 10:00");
+        assert_due("in 1 day noon", "2024-05-02 12:00");
+        assert_due("in 2 weeks", "2024-05-15 10:00");
+        assert_due("in 4 hours", "2024-05-01 14:00");
+        assert_due("in 30 mins", "2024-05-01 10:30");
+    }
+
+    #[test]
+    fn test_parse_time_only() {
+        assert_due("5pm", "2024-05-01 17:00");
+        assert_due("17:30", "2024-05-01 17:30");
+        assert_due("12am", "2024-05-02 00:00");
+        assert_due("9am", "2024-05-02 09:00");
+    }
+
+    #[test]
+    fn test_parse_iso() {
+        assert_due("2024-06-01", "2024-06-01 23:59");
+        assert_due("2024-06-01 08:15", "2024-06-01 08:15");
+        assert_due("2024-06-01T08:15", "2024-06-01 08:15");
+        assert_due("2024-06-01 8am", "2024-06-01 08:00");
+    }
+
+    fn assert_period(input: &str, expected: (&str, &str)) {
+        match parse_period(input, now()) {
+            Ok((start, end)) => {
+                let format = |moment: DateTime<Local>| moment.naive_local().format("%Y-%m-%d %H:%M").to_string();
+                assert_eq!((format(start).as_str(), format(end).as_str()), expected, "parsing '{}'", input);
+            }
+            Err(err) => {
+                panic!("Unexpected error for '{}': {:?}", input, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_period() {
+        assert_period("today", ("2024-05-01 00:00", "2024-05-02 00:00"));
+        assert_period("Yesterday", ("2024-04-30 00:00", "2024-05-01 00:00"));
+        assert_period("mon", ("2024-04-29 00:00", "2024-04-30 00:00"));
+        assert_period("last wed", ("2024-05-01 00:00", "2024-05-02 00:00"));
+        assert_period("2024-04-01", ("2024-04-01 00:00", "2024-04-02 00:00"));
+        assert_period("3 days ago", ("2024-04-28 10:00", "2024-04-28 10:00"));
+        assert_period("2024-04-01 17:30", ("2024-04-01 17:30", "2024-04-01 17:30"));
+        assert!(matches!(parse_period("in 3 days", now()), Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(parse_period("99999999999 days ago", now()), Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(parse_period("9999999999999999 weeks ago", now()), Err(NudgelystError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_parse_reminder_offset() {
+        assert_eq!(parse_reminder_offset("15m").unwrap(), 15);
+        assert_eq!(parse_reminder_offset("1h 30m").unwrap(), 90);
+        assert_eq!(parse_reminder_offset("2 days").unwrap(), 2880);
+        assert!(matches!(parse_reminder_offset("soon"), Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(parse_reminder_offset("500000000000000 days"), Err(NudgelystError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let too_far = [
+            "in 99999999999 days",
+            "in 9999999999999999 days",
+            "in 9999999999999 weeks",
+            "in 9223372036854775807 weeks",
+            "in 9999999999999999 hours",
+            "in 9999999999999999 minutes",
+        ];
+        for input in ["", "someday", "in three days", "in 2 fortnights", "13pm", "17", "2024-02-30", "fri 25:00"].into_iter().chain(too_far) {
+            // Act
+            let result = parse_due(input, now());
+
+            // Assert
+            assert!(matches!(result, Err(NudgelystError::InvalidInput(_))), "parsing '{}' gave {:?}", input, result);
+        }
+    }
+}
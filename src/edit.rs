@@ -0,0 +1,261 @@
+use chrono::{DateTime, Local};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+use crate::error::NudgelystError;
+use crate::recurrence::Recurrence;
+use crate::todo::{check_name, normalize_tag, Priority, Todo};
+
+/// Changes `edit` makes from its flags. Fields left as `None` are kept as they are.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TodoChanges {
+    pub name: Option<String>,
+    /// `Some(None)` removes the due date, along with its reminders.
+    pub due: Option<Option<DateTime<Local>>>,
+    pub remind_before: Option<Vec<u64>>,
+    /// `Some(None)` stops the todo from recurring.
+    pub recurrence: Option<Option<Recurrence>>,
+    pub priority: Option<Priority>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+}
+
+impl TodoChanges {
+    /// Whether no flags were given, in which case `edit` opens an editor instead.
+    pub fn is_empty(&self) -> bool {
+        *self == TodoChanges::default()
+    }
+
+    pub fn apply(self, todo: Todo) -> Result<Todo, NudgelystError> {
+        let mut todo = todo;
+        if let Some(name) = self.name {
+            todo.name = name;
+        }
+        if let Some(due) = self.due {
+            if due.is_none() {
+                todo.remind_before.clear();
+            }
+            todo.due = due;
+        }
+        if let Some(remind_before) = self.remind_before {
+            todo.remind_before = remind_before;
+        }
+        if let Some(recurrence) = self.recurrence {
+            todo.recurrence = recurrence;
+        }
+        if let Some(priority) = self.priority {
+            todo.priority = priority;
+        }
+        todo.tags.extend(self.add_tags);
+        todo.tags.retain(|tag| !self.remove_tags.iter().any(|removed| normalize_tag(removed) == *tag));
+
+        validate(todo)
+    }
+}
+
+/// Opens `todo` as YAML in `editor` and returns it as saved, or `None` if nothing was changed.
+///
+/// `editor` is run through `sh`, so it can carry arguments like `code --wait`. If the
+/// saved todo isn't valid the file is left in place so the edit isn't lost.
+pub fn edit_in_editor(todo: &Todo, editor: &str) -> Result<Option<Todo>, NudgelystError> {
+    let path = env::temp_dir().join(format!("nudgelyst-edit-{}-{}.yaml", process::id(), todo.id));
+    let yaml = serde_yaml::to_string(todo).map_err(|err| NudgelystError::Storage(err.to_string()))?;
+    fs::write(&path, format!(
+        "# Editing todo {}. Save and quit to apply; the id and revision can't be changed.\n{}",
+        todo.id, yaml,
+    ))?;
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .map_err(|err| NudgelystError::InvalidInput(format!("couldn't run editor `{}`: {}", editor, err)))?;
+    if !status.success() {
+        fs::remove_file(&path)?;
+        return Err(NudgelystError::InvalidInput(format!("editor `{}` failed with {}, todo left unchanged", editor, status)));
+    }
+
+    let edited = fs::read_to_string(&path)?;
+    let edited = parse_edited(todo, &edited).map_err(|err| kept_at(&path, err))?;
+    fs::remove_file(&path)?;
+
+    Ok(Some(edited).filter(|edited| edited != todo))
+}
+
+/// The editor `edit` opens: `$VISUAL`, then `$EDITOR`, then `vi`.
+pub fn default_editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+fn parse_edited(original: &Todo, yaml: &str) -> Result<Todo, NudgelystError> {
+    let edited: Todo = serde_yaml::from_str(yaml).map_err(|err| NudgelystError::InvalidInput(match err.location() {
+        Some(location) => format!("line {}: {}", location.line(), err),
+        None => err.to_string(),
+    }))?;
+    if edited.id != original.id {
+        return Err(NudgelystError::InvalidInput(format!("the id can't be changed (it was {})", original.id)));
+    }
+    if edited.list != original.list {
+        return Err(NudgelystError::InvalidInput("the list can't be changed here; use `move` instead".to_string()));
+    }
+
+    validate(Todo { revision: original.revision, ..edited })
+}
+
+/// Tidies up an edited todo and rejects the ones `todo` couldn't have created.
+fn validate(todo: Todo) -> Result<Todo, NudgelystError> {
+    let name = todo.name.trim().to_string();
+    if name.is_empty() {
+        return Err(NudgelystError::InvalidInput("a todo needs a name".to_string()));
+    }
+    check_name(&name)?;
+    if todo.due.is_none() && !todo.remind_before.is_empty() {
+        return Err(NudgelystError::InvalidInput("reminders need a due date".to_string()));
+    }
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in todo.tags.iter().map(|tag| normalize_tag(tag)) {
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    Ok(Todo { name, tags, ..todo })
+}
+
+fn kept_at(path: &Path, err: NudgelystError) -> NudgelystError {
+    match err {
+        NudgelystError::InvalidInput(message) => NudgelystError::InvalidInput(format!(
+            "{}; todo left unchanged and your edit is kept in {}",
+            message,
+            path.display(),
+        )),
+        err => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // each test uses its own id so their temporary files don't collide
+    fn todo(id: usize) -> Todo {
+        Todo {
+            revision: 4,
+            due: Some(Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap()),
+            remind_before: vec![30],
+            tags: vec!["home".to_string()],
+            ..Todo::new(id, "Buy milk".to_string())
+        }
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        // Arrange
+        let changes = TodoChanges {
+            name: Some("Buy oat milk ".to_string()),
+            due: Some(None),
+            priority: Some(Priority::High),
+            add_tags: vec!["+Errands".to_string()],
+            remove_tags: vec!["HOME".to_string()],
+            ..TodoChanges::default()
+        };
+
+        // Act
+        let result = changes.apply(todo(1));
+
+        // Assert
+        match result {
+            Ok(edited) => {
+                assert_eq!(edited.name, "Buy oat milk");
+                assert_eq!(edited.due, None);
+                assert!(edited.remind_before.is_empty());
+                assert_eq!(edited.priority, Priority::High);
+                assert_eq!(edited.tags, vec!["errands"]);
+                assert_eq!(edited.revision, 4);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_rejects_reminders_without_due() {
+        // Arrange
+        let changes = TodoChanges { remind_before: Some(vec![60]), ..TodoChanges::default() };
+
+        // Act
+        let result = changes.apply(Todo::new(1, "Call mum".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_apply_rejects_control_characters_in_name() {
+        // Arrange
+        let changes = TodoChanges { name: Some("tab\there\t…".to_string()), ..TodoChanges::default() };
+
+        // Act
+        let result = changes.apply(Todo::new(1, "Call mum".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_edit_in_editor() {
+        // Act
+        let result = edit_in_editor(&todo(2), "sed -i -e 's/Buy milk/Buy oat milk/' -e 's/revision: 4/revision: 9/'");
+
+        // Assert
+        match result {
+            Ok(edited) => {
+                let edited = edited.unwrap();
+                assert_eq!(edited.name, "Buy oat milk");
+                assert_eq!(edited.revision, 4);
+                assert_eq!(edited.due, todo(2).due);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edit_in_editor_without_changes() {
+        // Act
+        let result = edit_in_editor(&todo(3), "true");
+
+        // Assert
+        assert_eq!(result.unwrap(), None);
+    }
+
+    #[test]
+    fn test_edit_in_editor_rejects_changed_id() {
+        // Act
+        let result = edit_in_editor(&todo(4), "sed -i 's/^id: 4/id: 8/'");
+
+        // Assert
+        match result {
+            Err(NudgelystError::InvalidInput(message)) => {
+                let kept = message.rsplit(' ').next().unwrap();
+                assert!(message.starts_with("the id can't be changed"));
+                assert!(fs::read_to_string(kept).unwrap().contains("id: 8"));
+                fs::remove_file(kept).unwrap();
+            }
+            other => {
+                panic!("Unexpected result: {:?}", other);
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How a todo was looked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TodoRef {
+    Id(usize),
+    Name(String),
+}
+
+impl fmt::Display for TodoRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TodoRef::Id(id) => write!(f, "id '{}'", id),
+            TodoRef::Name(name) => write!(f, "name '{}'", name),
+        }
+    }
+}
+
+/// Everything that can go wrong in nudgelyst.
+#[derive(Debug)]
+pub enum NudgelystError {
+    /// No todo matches the given id or name.
+    NotFound(TodoRef),
+    /// Reading or writing storage failed.
+    Io(io::Error),
+    /// A file nudgelyst reads (the todo list, the config) couldn't be parsed.
+    Parse {
+        file: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+    /// The change clashes with what is already stored, e.g. a duplicate id.
+    Conflict(String),
+    /// The caller asked for something that doesn't make sense.
+    InvalidInput(String),
+    /// A storage backend failed in a way that isn't plain IO, e.g. a SQLite error.
+    Storage(String),
+    /// A nudge couldn't be sent.
+    Notify(String),
+}
+
+impl NudgelystError {
+    pub fn not_found_id(id: usize) -> NudgelystError {
+        NudgelystError::NotFound(TodoRef::Id(id))
+    }
+
+    pub fn not_found_name(name: impl Into<String>) -> NudgelystError {
+        NudgelystError::NotFound(TodoRef::Name(name.into()))
+    }
+
+    pub fn no_list(name: &str) -> NudgelystError {
+        NudgelystError::InvalidInput(format!("there's no list named `{}`; create it with `nudgelyst list create {}`", name, name))
+    }
+
+    /// An update or delete based on an older revision of a todo than the stored one.
+    pub fn stale_revision(id: usize, stored_revision: u64, read_revision: u64) -> NudgelystError {
+        NudgelystError::Conflict(format!(
+            "Todo with id '{}' has changed since it was read (revision {}, but the change is based on revision {})",
+            id, stored_revision, read_revision
+        ))
+    }
+
+    /// The process exit code the CLI uses for this error. Usage errors share
+    /// clap's code of 2.
+    ///
+    /// | code | meaning                                   |
+    /// |------|-------------------------------------------|
+    /// | 2    | invalid usage or input                    |
+    /// | 3    | todo not found                            |
+    /// | 4    | conflict with what is already stored      |
+    /// | 5    | storage error (IO, parsing, the database) |
+    /// | 6    | a nudge couldn't be sent                  |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            NudgelystError::InvalidInput(_) => 2,
+            NudgelystError::NotFound(_) => 3,
+            NudgelystError::Conflict(_) => 4,
+            NudgelystError::Io(_) | NudgelystError::Parse { .. } | NudgelystError::Storage(_) => 5,
+            NudgelystError::Notify(_) => 6,
+        }
+    }
+
+    pub(crate) fn yaml(file: &Path, err: serde_yaml::Error) -> NudgelystError {
+        NudgelystError::Parse {
+            file: file.to_path_buf(),
+            line: err.location().map(|location| location.line()),
+            message: err.to_string(),
+        }
+    }
+
+    /// Also names the key on the line the error points at, e.g. `theme.done`.
+    pub(crate) fn toml(file: &Path, content: &str, err: toml::de::Error) -> NudgelystError {
+        let line = err
+            .span()
+            .map(|span| content[..span.start].matches('\n').count() + 1);
+        let message = match line.and_then(|line| toml_key_on_line(content, line)) {
+            Some(key) => format!("{}: {}", key, err.message()),
+            None => err.message().to_string(),
+        };
+
+        NudgelystError::Parse {
+            file: file.to_path_buf(),
+            line,
+            message,
+        }
+    }
+}
+
+/// The dotted key set or table opened on `line` of a TOML file, going by the
+/// table header above it.
+fn toml_key_on_line(content: &str, line: usize) -> Option<String> {
+    let mut table = String::new();
+    for text in content.lines().take(line - 1) {
+        if let Some(header) = table_header(text) {
+            table = header;
+        }
+    }
+
+    let text = content.lines().nth(line - 1)?;
+    if let Some(header) = table_header(text) {
+        return Some(header);
+    }
+    let (key, _) = text.split_once('=')?;
+    let key = key.trim();
+    match table.is_empty() {
+        true => Some(key.to_string()),
+        false => Some(format!("{}.{}", table, key)),
+    }
+}
+
+fn table_header(text: &str) -> Option<String> {
+    let text = text.trim();
+    if !text.starts_with('[') {
+        return None;
+    }
+
+    text.trim_start_matches('[').split(']').next().map(|header| header.trim().to_string())
+}
+
+impl fmt::Display for NudgelystError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NudgelystError::NotFound(todo_ref) => write!(f, "Todo with {} not found", todo_ref),
+            NudgelystError::Io(err) => write!(f, "{}", err),
+            NudgelystError::Parse { file, line: Some(line), message } => {
+                write!(f, "{}:{}: {}", file.display(), line, message)
+            }
+            NudgelystError::Parse { file, line: None, message } => write!(f, "{}: {}", file.display(), message),
+            NudgelystError::Conflict(message) => write!(f, "{}", message),
+            NudgelystError::InvalidInput(message) => write!(f, "{}", message),
+            NudgelystError::Storage(message) => write!(f, "{}", message),
+            NudgelystError::Notify(message) => write!(f, "couldn't send nudge: {}", message),
+        }
+    }
+}
+
+impl Error for NudgelystError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NudgelystError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NudgelystError {
+    fn from(err: io::Error) -> NudgelystError {
+        NudgelystError::Io(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for NudgelystError {
+    fn from(err: rusqlite::Error) -> NudgelystError {
+        match err.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::ConstraintViolation) => NudgelystError::Conflict(err.to_string()),
+            _ => NudgelystError::Storage(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_found_message() {
+        // Act
+        let by_id = NudgelystError::not_found_id(3).to_string();
+        let by_name = NudgelystError::not_found_name("Task 1").to_string();
+
+        // Assert
+        assert_eq!(by_id, "Todo with id '3' not found");
+        assert_eq!(by_name, "Todo with name 'Task 1' not found");
+    }
+
+    #[test]
+    fn test_yaml_parse_error_has_line() {
+        // Arrange
+        let content = "last_id: 1\ntodos:\n- id: one\n";
+        let err = serde_yaml::from_str::<crate::todo_store::TodoStore>(content).unwrap_err();
+
+        // Act
+        let result = NudgelystError::yaml(Path::new("nudgelyst.yaml"), err);
+
+        // Assert
+        match result {
+            NudgelystError::Parse { file, line, .. } => {
+                assert_eq!(file, PathBuf::from("nudgelyst.yaml"));
+                assert_eq!(line, Some(3));
+            }
+            other => {
+                panic!("Unexpected error: {:?}", other);
+            }
+        }
+    }
+
+    #[test]
+    fn test_toml_parse_error_has_line() {
+        // Arrange
+        let content = "[storage]\npath = \n";
+        let err = toml::from_str::<toml::Value>(content).unwrap_err();
+
+        // Act
+        let result = NudgelystError::toml(Path::new("config.toml"), content, err);
+
+        // Assert
+        match result {
+            NudgelystError::Parse { line, .. } => {
+                assert_eq!(line, Some(2));
+            }
+            other => {
+                panic!("Unexpected error: {:?}", other);
+            }
+        }
+    }
+
+    #[test]
+    fn test_toml_parse_error_names_key() {
+        // Arrange
+        let content = "[storage]\npath = \"/tmp/todos.yaml\"\n\n[theme]\ndone = \"mauve\"\n";
+        let err = toml::from_str::<crate::config::Config>(content).unwrap_err();
+
+        // Act
+        let result = NudgelystError::toml(Path::new("config.toml"), content, err);
+
+        // Assert
+        match result {
+            NudgelystError::Parse { line, message, .. } => {
+                assert_eq!(line, Some(5));
+                assert!(message.starts_with("theme.done: unknown color `mauve`"), "{}", message);
+            }
+            other => {
+                panic!("Unexpected error: {:?}", other);
+            }
+        }
+    }
+}
@@ -1,48 +1,145 @@
-use std::error::Error;
-use std::fs;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::error::NudgelystError;
+use crate::lists::TodoList;
 use crate::todo::{self, Todo, TodoRepository};
+use crate::todo_store::{StoredTodos, TodoStore};
 
-#[derive(Debug)]
-pub struct FileTodoRepository;
+/// Stores todos as YAML in a single file, along with the last id handed out.
+#[derive(Debug, Clone)]
+pub struct FileTodoRepository {
+    path: PathBuf,
+}
+
+impl FileTodoRepository {
+    pub fn new(path: impl Into<PathBuf>) -> FileTodoRepository {
+        FileTodoRepository { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creates an empty todo list file, failing if one already exists.
+    pub fn init(&self) -> Result<(), NudgelystError> {
+        let _lock = self.lock()?;
+        if self.path.exists() {
+            return Err(NudgelystError::Conflict(format!("{} already exists", self.path.display())));
+        }
+
+        self.write_to_file(&TodoStore::default())
+    }
+
+    /// Runs a read-modify-write of the whole list while holding an exclusive lock,
+    /// so concurrent nudgelyst processes can't lose each other's updates.
+    fn modify_todos<F>(&self, modify: F) -> Result<(), NudgelystError>
+    where
+        F: FnOnce(&mut TodoStore) -> Result<(), NudgelystError>,
+    {
+        let _lock = self.lock()?;
+        let mut store = self.read_from_file()?;
+        modify(&mut store)?;
+
+        self.write_to_file(&store)
+    }
+
+    /// Takes an advisory lock on a `.lock` file next to the list. The list itself
+    /// can't be locked since every write replaces it with a new file.
+    /// The lock is released when the returned file is dropped.
+    fn lock(&self) -> Result<File, NudgelystError> {
+        self.create_parent_dir()?;
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sibling_path(&self.path, ".lock"))?;
+        lock_file.lock()?;
+
+        Ok(lock_file)
+    }
+
+    // Reads don't need the lock - writes replace the file atomically, so a reader
+    // always sees either the old or the new list in full.
+    pub(crate) fn read_from_file(&self) -> Result<TodoStore, NudgelystError> {
+        if !self.path.exists() {
+            return Ok(TodoStore::default());
+        }
+
+        let existing_content = fs::read_to_string(&self.path)?;
+        let stored: StoredTodos = serde_yaml::from_str(&existing_content)
+            .map_err(|err| NudgelystError::yaml(&self.path, err))?;
+
+        Ok(stored.into())
+    }
+
+    /// Writes the list to a temp file and renames it over the original, so a crash
+    /// mid-write leaves the previous list intact rather than a truncated one.
+    fn write_to_file(&self, store: &TodoStore) -> Result<(), NudgelystError> {
+        self.create_parent_dir()?;
+        // write through symlinks rather than replacing them
+        let target_path = fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        let tmp_path = sibling_path(&target_path, ".tmp");
+
+        let updated_content = serde_yaml::to_string(store)
+            .map_err(|err| NudgelystError::Storage(err.to_string()))?;
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(updated_content.as_bytes())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &target_path)?;
+
+        Ok(())
+    }
+
+    fn create_parent_dir(&self) -> Result<(), NudgelystError> {
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `nudgelyst.yaml` + `.lock` -> `nudgelyst.yaml.lock`
+pub(crate) fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+
+    path.with_file_name(file_name)
+}
 
 impl TodoRepository for FileTodoRepository {
-    fn get_todo(&self, id: usize) -> Result<Todo, Box<dyn Error>> {
+    fn get_todo(&self, id: usize) -> Result<Todo, NudgelystError> {
         let all_todos = todo::get_todos(self, None)?;
 
         all_todos
             .iter()
             .find(|&todo| todo.id == id)
-            .map(|todo| todo.clone())
-            .ok_or_else(|| Box::from("Todo not found"))
+            .cloned()
+            .ok_or_else(|| NudgelystError::not_found_id(id))
     }
 
-    fn get_todo_by_name(&self, name: String) -> Result<Todo, Box<dyn Error>> {
+    fn get_todo_by_name(&self, name: String) -> Result<Todo, NudgelystError> {
         let all_todos = todo::get_todos(self, None)?;
 
         all_todos
             .iter()
             .find(|&todo| todo.name == name)
-            .map(|todo| todo.clone())
-            .ok_or_else(|| Box::from("Todo not found"))
+            .cloned()
+            .ok_or_else(|| NudgelystError::not_found_name(name))
     }
 
-    fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
-        let file_path = "nudgelyst.yaml";
-        if Path::new(file_path).exists() {
-            let existing_content = fs::read_to_string(file_path)?;
-            let mut existing_tasks: Vec<Todo> = serde_yaml::from_str(&existing_content)?;
-            if let Some(limit) = limit {
-                existing_tasks = existing_tasks.iter().cloned().take(limit).collect();
-            }
-
-            return Ok(existing_tasks);
+    fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        let mut existing_tasks = self.read_from_file()?.todos;
+        if let Some(limit) = limit {
+            existing_tasks.truncate(limit);
         }
 
-        Ok(Vec::new())
+        Ok(existing_tasks)
     }
 
-    fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
+    fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
         let todos = todo::get_todos(self, None)?;
 
         let completed_todos: Vec<Todo> = if let Some(limit) = limit {
@@ -61,7 +158,7 @@ impl TodoRepository for FileTodoRepository {
         Ok(completed_todos)
     }
 
-    fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
+    fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
         let todos = todo::get_todos(self, None)?;
 
         let incomplete_todos: Vec<Todo> = if let Some(limit) = limit {
@@ -80,222 +177,145 @@ impl TodoRepository for FileTodoRepository {
         Ok(incomplete_todos)
     }
 
-    fn create_todo(&self, mut todo: Todo) -> Result<(), Box<dyn Error>> {
-        let mut existing_todos = todo::get_todos(self, None)?;
+    fn get_todos_with_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        let todos = todo::get_todos(self, None)?;
 
-        // generate file id
-        if todo.id == 0 {
-            todo.id = existing_todos.len() + 1;
-        }
+        Ok(todos
+            .into_iter()
+            .filter(|todo| todo.has_tag(&tag))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
+    }
 
-        existing_todos.push(todo);
+    fn get_todos_without_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        let todos = todo::get_todos(self, None)?;
 
-        Ok(write_to_file(existing_todos)?)
+        Ok(todos
+            .into_iter()
+            .filter(|todo| !todo.has_tag(&tag))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
     }
 
-    fn create_todos(&self, mut todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
-        let mut existing_todos = todo::get_todos(self, None)?;
-
-        // Calculate ids for todos with id 0
-        let mut last_id = existing_todos.last().map(|todo| todo.id).unwrap_or(0);
+    fn get_tag_counts(&self) -> Result<BTreeMap<String, usize>, NudgelystError> {
+        Ok(self.read_from_file()?.tag_counts())
+    }
 
-        for todo in &mut todos {
-            if todo.id == 0 {
-                todo.id = last_id + 1;
-                last_id += 1;
-            }
-        }
+    fn get_todos_in_list(&self, list: &str, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        let todos = todo::get_todos(self, None)?;
 
-        existing_todos.extend(todos);
+        Ok(todos
+            .into_iter()
+            .filter(|todo| todo.list_name() == list)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
+    }
 
-        Ok(write_to_file(existing_todos)?)
+    fn get_lists(&self) -> Result<Vec<TodoList>, NudgelystError> {
+        Ok(self.read_from_file()?.lists())
     }
 
-    fn update_todo(&self, todo: Todo) -> Result<(), Box<dyn Error>> {
-        let mut existing_todos = todo::get_todos(self, None)?;
+    fn create_list(&self, name: &str) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| store.create_list(name))
+    }
 
-        if let Some(existing_todo) = existing_todos.iter_mut().find(|t| t.id == todo.id) {
-            *existing_todo = todo.clone();
-        }
+    fn rename_list(&self, from: &str, to: &str) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| store.rename_list(from, to))
+    }
 
-        Ok(write_to_file(existing_todos)?)
+    fn delete_list(&self, name: &str) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| store.delete_list(name))
     }
 
-    fn update_todos(&self, todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
-        let mut existing_todos = todo::get_todos(self, None)?;
+    fn move_todos(&self, todos: Vec<Todo>, to: &str) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| store.move_todos(&todos, to))
+    }
 
-        for new_todo in &todos {
-            if let Some(existing_todo) = existing_todos.iter_mut().find(|t| t.id == new_todo.id) {
-                *existing_todo = new_todo.clone();
-            }
-        }
+    fn create_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| {
+            store.insert(todo)?;
 
-        Ok(write_to_file(existing_todos)?)
+            Ok(())
+        })
     }
 
-    fn delete_todo(&self, todo: Todo) -> Result<(), Box<dyn Error>> {
-        let todos = todo::get_todos(self, None)?;
-        let new_todos: Vec<Todo> = todos
-            .iter()
-            .filter(|&todo_in_store| todo_in_store.id != todo.id)
-            .cloned()
-            .collect();
+    fn create_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| {
+            for todo in todos {
+                store.insert(todo)?;
+            }
 
-        // write the remaining to file
-        write_to_file(new_todos)
+            Ok(())
+        })
     }
 
-    fn delete_todos(&self, todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
-        let mut existing_todos = todo::get_todos(self, None)?;
-
-        // Extract the ids of todos to be deleted
-        let todos_to_delete_ids: Vec<usize> = todos.iter().map(|todo| todo.id).collect();
+    fn update_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| store.update(todo))
+    }
 
-        // Filter out todos with matching ids
-        existing_todos.retain(|todo| !todos_to_delete_ids.contains(&todo.id));
+    fn update_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| {
+            for todo in todos {
+                store.update(todo)?;
+            }
 
-        // write todos in storage
-        write_to_file(existing_todos)?;
+            Ok(())
+        })
+    }
 
-        Ok(())
+    fn delete_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| store.remove(&todo))
     }
-}
 
-fn write_to_file(todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
-    let file_path = "nudgelyst.yaml";
-    let updated_content = serde_yaml::to_string(&todos)?;
-    fs::write(file_path, updated_content)?;
+    fn delete_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.modify_todos(|store| {
+            for todo in &todos {
+                store.remove(todo)?;
+            }
 
-    Ok(())
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::error::Error;
-
-    // Mock implementation for FileTodoRepository for testing purposes
-    struct MockFileTodoRepository;
+    use crate::error::TodoRef;
+    use crate::in_memory_todo_repository::InMemoryTodoRepository;
+    use chrono::{Local, TimeZone};
 
     // Define a constant array of string literals
     const MOCK_TODO_NAMES: [&str; 3] = ["Task 1", "Task 2", "Task 3"];
 
     fn get_mock_todos() -> Vec<Todo> {
-        // Convert string literals to String
-        let todos: Vec<Todo> = MOCK_TODO_NAMES
+        // Stamped up front, so the repository keeps them as they are
+        let stamped = Some(Local.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap());
+
+        MOCK_TODO_NAMES
             .iter()
             .enumerate()
-            .map(|(id, name)| Todo {
-                id: id + 1,
-                name: name.to_string(),
-                completed: id % 2 == 1, // Every other task is completed for variety
+            .map(|(id, name)| {
+                let completed = id % 2 == 1; // Every other task is completed for variety
+                Todo {
+                    completed,
+                    created_at: stamped,
+                    updated_at: stamped,
+                    completed_at: stamped.filter(|_| completed),
+                    ..Todo::new(id + 1, name.to_string())
+                }
             })
-            .collect();
-
-        todos
+            .collect()
     }
 
-    impl TodoRepository for MockFileTodoRepository {
-        fn get_todo(&self, id: usize) -> Result<Todo, Box<dyn Error>> {
-            // Simulate existing todos in the repository
-            let existing_todos = get_mock_todos();
-
-            // Find the todo with the specified ID
-            if let Some(todo) = existing_todos.iter().find(|&t| t.id == id) {
-                Ok(todo.clone())
-            } else {
-                Err(Box::from("Todo not found"))
-            }
-        }
-
-        fn get_todo_by_name(&self, name: String) -> Result<Todo, Box<dyn Error>> {
-            // Simulate existing todos in the repository
-            let existing_todos = get_mock_todos();
-
-            // Find the todo with the specified name
-            if let Some(todo) = existing_todos.iter().find(|&t| t.name == name) {
-                Ok(todo.clone())
-            } else {
-                Err(Box::from("Todo not found"))
-            }
-        }
-
-        fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
-            // Simulate existing todos in the repository
-            let existing_todos = get_mock_todos();
-
-            // Apply the limit if provided
-            let filtered_todos = match limit {
-                Some(l) => existing_todos.iter().cloned().take(l).collect(),
-                None => existing_todos,
-            };
-
-            Ok(filtered_todos)
-        }
-
-        fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
-            // Simulate existing todos in the repository
-            let existing_todos: Vec<Todo> = get_mock_todos()
-                .iter()
-                .cloned()
-                .filter(|todo| todo.completed)
-                .collect();
-
-            // Apply the limit if provided
-            let filtered_todos = match limit {
-                Some(l) => existing_todos.iter().cloned().take(l).collect(),
-                None => existing_todos,
-            };
-
-            Ok(filtered_todos)
-        }
-
-        fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
-            // Simulate existing todos in the repository
-            let existing_todos: Vec<Todo> = get_mock_todos()
-                .iter()
-                .cloned()
-                .filter(|todo| !todo.completed)
-                .collect();
-
-            // Apply the limit if provided
-            let filtered_todos = match limit {
-                Some(l) => existing_todos.iter().cloned().take(l).collect(),
-                None => existing_todos,
-            };
-
-            Ok(filtered_todos)
-        }
-
-        fn create_todo(&self, _todo: Todo) -> Result<(), Box<dyn Error>> {
-            todo!()
-        }
-
-        fn create_todos(&self, _todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
-            todo!()
-        }
-
-        fn update_todo(&self, _todo: Todo) -> Result<(), Box<dyn Error>> {
-            todo!()
-        }
-
-        fn update_todos(&self, _todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
-            todo!()
-        }
-
-        fn delete_todo(&self, _todo: Todo) -> Result<(), Box<dyn Error>> {
-            todo!()
-        }
-
-        fn delete_todos(&self, _todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
-            todo!()
-        }
+    fn mock_repo() -> InMemoryTodoRepository {
+        InMemoryTodoRepository::with_todos(get_mock_todos()).unwrap()
     }
 
     #[test]
     fn test_get_todo_success() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
         let todo_id = 1;
 
         // Act
@@ -315,7 +335,7 @@ mod tests {
     #[test]
     fn test_get_todo_not_found() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
         let todo_id = 999; // Some non-existent ID
 
         // Act
@@ -327,7 +347,7 @@ mod tests {
                 panic!("Expected error, but got Ok");
             }
             Err(err) => {
-                assert_eq!(err.to_string(), "Todo not found".to_string());
+                assert!(matches!(err, NudgelystError::NotFound(TodoRef::Id(999))));
             }
         }
     }
@@ -335,7 +355,7 @@ mod tests {
     #[test]
     fn test_get_todo_by_name_success() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
         let todo_name = "Task 1".to_string();
 
         // Act
@@ -355,7 +375,7 @@ mod tests {
     #[test]
     fn test_get_todo_by_name_not_found() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
         let todo_name = "".to_string();
 
         // Act
@@ -367,7 +387,7 @@ mod tests {
                 panic!("Expected error, but got Ok");
             }
             Err(err) => {
-                assert_eq!(err.to_string(), "Todo not found".to_string());
+                assert!(matches!(err, NudgelystError::NotFound(TodoRef::Name(name)) if name.is_empty()));
             }
         }
     }
@@ -375,7 +395,7 @@ mod tests {
     #[test]
     fn test_get_todos_with_limit() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
         let limit = Some(2);
 
         // Act
@@ -395,7 +415,7 @@ mod tests {
     #[test]
     fn test_get_todos_no_limit() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
 
         // Act
         let result = repo.get_todos(None);
@@ -416,7 +436,7 @@ mod tests {
     #[test]
     fn test_get_completed_todos_with_limit() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
         let limit = Some(2);
 
         // Act
@@ -436,15 +456,15 @@ mod tests {
     #[test]
     fn test_get_completed_todos_no_limit() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
 
         // Act
         let result = repo.get_completed_todos(None);
 
         let existing_completed_todos: Vec<Todo> = get_mock_todos()
             .iter()
-            .cloned()
             .filter(|todo| todo.completed)
+            .cloned()
             .collect();
 
         // Assert
@@ -461,7 +481,7 @@ mod tests {
     #[test]
     fn test_get_incomplete_todos_with_limit() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
         let limit = Some(2);
 
         // Act
@@ -481,15 +501,15 @@ mod tests {
     #[test]
     fn test_get_incomplete_todos_no_limit() {
         // Arrange
-        let repo = MockFileTodoRepository;
+        let repo = mock_repo();
 
         // Act
         let result = repo.get_incomplete_todos(None);
 
         let existing_incomplete_todos: Vec<Todo> = get_mock_todos()
             .iter()
-            .cloned()
             .filter(|todo| !todo.completed)
+            .cloned()
             .collect();
 
         // Assert
@@ -502,4 +522,330 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_get_todos_missing_file() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+
+        // Act
+        let result = repo.get_todos(None);
+
+        // Assert
+        match result {
+            Ok(todos) => {
+                assert!(todos.is_empty());
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_todo_writes_to_repository_path() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("nested").join("todos.yaml");
+        let repo = FileTodoRepository::new(&file_path);
+
+        // Act
+        let result = repo.create_todo(Todo::new(0, "Task 1".to_string()));
+
+        // Assert
+        match result {
+            Ok(()) => {
+                assert!(file_path.exists());
+                let todos = repo.get_todos(None).unwrap();
+                assert_eq!(todos.len(), 1);
+                assert_eq!(Todo { created_at: None, updated_at: None, ..todos[0].clone() }, Todo::new(1, "Task 1".to_string()));
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_init_creates_empty_list() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+
+        // Act
+        let result = repo.init();
+
+        // Assert
+        match result {
+            Ok(()) => {
+                assert!(repo.path().exists());
+                assert!(repo.get_todos(None).unwrap().is_empty());
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_init_existing_list() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+
+        // Act
+        let result = repo.init();
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_write_leaves_no_temp_file() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+
+        // Act
+        let result = repo.create_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo::new(0, "Task 2".to_string()),
+        ]);
+
+        // Assert
+        match result {
+            Ok(()) => {
+                assert!(!dir.path().join("nudgelyst.yaml.tmp").exists());
+                assert_eq!(repo.get_todos(None).unwrap().len(), 2);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_through_symlink() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("real.yaml");
+        let link = dir.path().join("nudgelyst.yaml");
+        fs::write(&target, "[]").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let repo = FileTodoRepository::new(&link);
+
+        // Act
+        let result = repo.create_todo(Todo::new(0, "Task 1".to_string()));
+
+        // Assert
+        match result {
+            Ok(()) => {
+                assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+                assert!(fs::read_to_string(&target).unwrap().contains("Task 1"));
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_todo_does_not_reuse_deleted_ids() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+        repo.create_todo(Todo::new(0, "Task 2".to_string())).unwrap();
+        repo.delete_todo(repo.get_todo(1).unwrap()).unwrap();
+
+        // Act
+        let result = repo.create_todo(Todo::new(0, "Task 3".to_string()));
+
+        // Assert
+        match result {
+            Ok(()) => {
+                let ids: Vec<usize> = repo.get_todos(None).unwrap().iter().map(|todo| todo.id).collect();
+                assert_eq!(ids, vec![2, 3]);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_todos_shares_id_allocation() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        repo.create_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo::new(0, "Task 2".to_string()),
+            Todo::new(0, "Task 3".to_string()),
+        ]).unwrap();
+        repo.delete_todos(repo.get_todos(None).unwrap()).unwrap();
+
+        // Act
+        let result = repo.create_todos(vec![Todo::new(0, "Task 4".to_string())]);
+
+        // Assert
+        match result {
+            Ok(()) => {
+                let todos = repo.get_todos(None).unwrap();
+                assert_eq!(todos.len(), 1);
+                assert_eq!(Todo { created_at: None, updated_at: None, ..todos[0].clone() }, Todo::new(4, "Task 4".to_string()));
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_todo_duplicate_id() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+
+        // Act
+        let result = repo.create_todo(Todo::new(1, "Task 1 again".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_legacy_file_keeps_loading() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("nudgelyst.yaml");
+        fs::write(&file_path, "- id: 1\n  name: Task 1\n  completed: false\n- id: 3\n  name: Task 3\n  completed: true\n").unwrap();
+        let repo = FileTodoRepository::new(&file_path);
+
+        // Act
+        let result = repo.create_todo(Todo::new(0, "Task 4".to_string()));
+
+        // Assert
+        match result {
+            Ok(()) => {
+                let ids: Vec<usize> = repo.get_todos(None).unwrap().iter().map(|todo| todo.id).collect();
+                assert_eq!(ids, vec![1, 3, 4]);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_todo_missing_does_not_write() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+
+        // Act
+        let result = repo.update_todo(Todo::new(1, "Task 1".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::NotFound(TodoRef::Id(1)))));
+        assert!(!repo.path().exists());
+    }
+
+    #[test]
+    fn test_delete_todo_missing() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+
+        // Act
+        let result = repo.delete_todo(Todo::new(2, "Task 2".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::NotFound(TodoRef::Id(2)))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_update_todo_from_stale_read() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+        let first_read = repo.get_todo(1).unwrap();
+        let second_read = repo.get_todo(1).unwrap();
+        repo.update_todo(Todo { name: "Renamed".to_string(), ..first_read }).unwrap();
+
+        // Act
+        let result = repo.update_todo(Todo { completed: true, ..second_read });
+
+        // Assert
+        match result {
+            Ok(()) => {
+                panic!("Expected error, but got Ok");
+            }
+            Err(err) => {
+                assert!(matches!(err, NudgelystError::Conflict(_)));
+                let stored = repo.get_todo(1).unwrap();
+                assert_eq!(stored.name, "Renamed");
+                assert_eq!(stored.revision, 1);
+                assert!(!stored.completed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_due_date_round_trips() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        let due = Local.with_ymd_and_hms(2024, 5, 3, 17, 0, 0).unwrap();
+
+        // Act
+        let result = repo.create_todos(vec![
+            Todo { due: Some(due), ..Todo::new(0, "Task 1".to_string()) },
+            Todo::new(0, "Task 2".to_string()),
+        ]);
+
+        // Assert
+        match result {
+            Ok(()) => {
+                assert_eq!(repo.get_todo(1).unwrap().due, Some(due));
+                assert_eq!(repo.get_todo(2).unwrap().due, None);
+                let content = fs::read_to_string(repo.path()).unwrap();
+                assert_eq!(content.matches("due:").count(), 1);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tag_queries() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        repo.create_todos(vec![
+            Todo { tags: vec!["work".to_string(), "urgent".to_string()], ..Todo::new(0, "Fix CI".to_string()) },
+            Todo { tags: vec!["work".to_string()], ..Todo::new(0, "Review PR".to_string()) },
+            Todo::new(0, "Buy milk".to_string()),
+        ]).unwrap();
+
+        // Act
+        let with_work = repo.get_todos_with_tag("+Work".to_string(), None);
+        let without_work = repo.get_todos_without_tag("work".to_string(), None);
+        let counts = repo.get_tag_counts();
+
+        // Assert
+        assert_eq!(with_work.unwrap().iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(without_work.unwrap().iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(counts.unwrap(), BTreeMap::from([("urgent".to_string(), 1), ("work".to_string(), 2)]));
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use crate::error::NudgelystError;
+use crate::lists::TodoList;
+use crate::todo::{Todo, TodoRepository};
+use crate::todo_store::TodoStore;
+
+/// Keeps todos in memory only, for embedding nudgelyst and for tests.
+///
+/// Ids are allocated the same way as in the file backend, so they are never
+/// reused for the lifetime of the repository. The repository can be shared
+/// between threads.
+#[derive(Debug, Default)]
+pub struct InMemoryTodoRepository {
+    store: Mutex<TodoStore>,
+}
+
+impl InMemoryTodoRepository {
+    pub fn new() -> InMemoryTodoRepository {
+        InMemoryTodoRepository::default()
+    }
+
+    /// Starts the repository off with `todos`, allocating ids for any with an id of 0.
+    pub fn with_todos(todos: Vec<Todo>) -> Result<InMemoryTodoRepository, NudgelystError> {
+        let repo = InMemoryTodoRepository::new();
+        repo.create_todos(todos)?;
+
+        Ok(repo)
+    }
+
+    fn store(&self) -> MutexGuard<'_, TodoStore> {
+        // a panic while holding the lock can't leave the store half-written,
+        // since every mutation below is applied with a single call
+        self.store.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Applies a batch of changes to a copy of the store, so an error part way
+    /// through leaves the repository untouched.
+    fn modify_all<F>(&self, modify: F) -> Result<(), NudgelystError>
+    where
+        F: FnOnce(&mut TodoStore) -> Result<(), NudgelystError>,
+    {
+        let mut store = self.store();
+        let mut updated_store = store.clone();
+        modify(&mut updated_store)?;
+        *store = updated_store;
+
+        Ok(())
+    }
+
+    fn filter_todos<P>(&self, limit: Option<usize>, predicate: P) -> Vec<Todo>
+    where
+        P: Fn(&Todo) -> bool,
+    {
+        self.store()
+            .todos
+            .iter()
+            .filter(|&todo| predicate(todo))
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+impl TodoRepository for InMemoryTodoRepository {
+    fn get_todo(&self, id: usize) -> Result<Todo, NudgelystError> {
+        self.store()
+            .todos
+            .iter()
+            .find(|&todo| todo.id == id)
+            .cloned()
+            .ok_or_else(|| NudgelystError::not_found_id(id))
+    }
+
+    fn get_todo_by_name(&self, name: String) -> Result<Todo, NudgelystError> {
+        self.store()
+            .todos
+            .iter()
+            .find(|&todo| todo.name == name)
+            .cloned()
+            .ok_or_else(|| NudgelystError::not_found_name(name))
+    }
+
+    fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        Ok(self.filter_todos(limit, |_| true))
+    }
+
+    fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        Ok(self.filter_todos(limit, |todo| todo.completed))
+    }
+
+    fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        Ok(self.filter_todos(limit, |todo| !todo.completed))
+    }
+
+    fn get_todos_with_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        Ok(self.filter_todos(limit, |todo| todo.has_tag(&tag)))
+    }
+
+    fn get_todos_without_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        Ok(self.filter_todos(limit, |todo| !todo.has_tag(&tag)))
+    }
+
+    fn get_tag_counts(&self) -> Result<BTreeMap<String, usize>, NudgelystError> {
+        Ok(self.store().tag_counts())
+    }
+
+    fn get_todos_in_list(&self, list: &str, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        Ok(self.filter_todos(limit, |todo| todo.list_name() == list))
+    }
+
+    fn get_lists(&self) -> Result<Vec<TodoList>, NudgelystError> {
+        Ok(self.store().lists())
+    }
+
+    fn create_list(&self, name: &str) -> Result<(), NudgelystError> {
+        self.store().create_list(name)
+    }
+
+    fn rename_list(&self, from: &str, to: &str) -> Result<(), NudgelystError> {
+        self.modify_all(|store| store.rename_list(from, to))
+    }
+
+    fn delete_list(&self, name: &str) -> Result<(), NudgelystError> {
+        self.modify_all(|store| store.delete_list(name))
+    }
+
+    fn move_todos(&self, todos: Vec<Todo>, to: &str) -> Result<(), NudgelystError> {
+        self.modify_all(|store| store.move_todos(&todos, to))
+    }
+
+    fn create_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.store().insert(todo)?;
+
+        Ok(())
+    }
+
+    fn create_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.modify_all(|store| {
+            for todo in todos {
+                store.insert(todo)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn update_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.store().update(todo)
+    }
+
+    fn update_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.modify_all(|store| {
+            for todo in todos {
+                store.update(todo)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.store().remove(&todo)
+    }
+
+    fn delete_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.modify_all(|store| {
+            for todo in &todos {
+                store.remove(todo)?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::TodoRef;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn repo_with_tasks() -> InMemoryTodoRepository {
+        InMemoryTodoRepository::with_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo::new(0, "Task 2".to_string()),
+            Todo::new(0, "Task 3".to_string()),
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_get_todo_not_found() {
+        // Arrange
+        let repo = repo_with_tasks();
+
+        // Act
+        let result = repo.get_todo(999);
+
+        // Assert
+        match result {
+            Ok(_) => {
+                panic!("Expected error, but got Ok");
+            }
+            Err(err) => {
+                assert!(matches!(err, NudgelystError::NotFound(TodoRef::Id(999))));
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_and_filter_todos() {
+        // Arrange
+        let repo = repo_with_tasks();
+        let todo = repo.get_todo_by_name("Task 2".to_string()).unwrap();
+
+        // Act
+        repo.update_todo(Todo { completed: true, ..todo }).unwrap();
+
+        // Assert
+        assert_eq!(repo.get_todo(2).unwrap().revision, 1);
+        assert_eq!(repo.get_completed_todos(None).unwrap().len(), 1);
+        assert_eq!(repo.get_incomplete_todos(None).unwrap().len(), 2);
+        assert_eq!(repo.get_incomplete_todos(Some(1)).unwrap().len(), 1);
+        assert!(repo.get_todo(2).unwrap().completed);
+    }
+
+    #[test]
+    fn test_ids_are_not_reused_after_delete() {
+        // Arrange
+        let repo = repo_with_tasks();
+        repo.delete_todos(repo.get_todos(None).unwrap()).unwrap();
+
+        // Act
+        repo.create_todo(Todo::new(0, "Task 4".to_string())).unwrap();
+
+        // Assert
+        let todos = repo.get_todos(None).unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(Todo { created_at: None, updated_at: None, ..todos[0].clone() }, Todo::new(4, "Task 4".to_string()));
+    }
+
+    #[test]
+    fn test_create_todos_duplicate_id_leaves_repository_untouched() {
+        // Arrange
+        let repo = repo_with_tasks();
+
+        // Act
+        let result = repo.create_todos(vec![
+            Todo::new(0, "Task 4".to_string()),
+            Todo::new(1, "Task 1 again".to_string()),
+        ]);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_update_todos_stale_revision_leaves_repository_untouched() {
+        // Arrange
+        let repo = repo_with_tasks();
+        let stale = repo.get_todo(2).unwrap();
+        repo.update_todo(Todo { name: "Renamed".to_string(), ..stale.clone() }).unwrap();
+
+        // Act
+        let result = repo.update_todos(vec![
+            Todo { completed: true, ..repo.get_todo(1).unwrap() },
+            Todo { completed: true, ..stale },
+        ]);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert!(repo.get_completed_todos(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delete_missing_todo() {
+        // Arrange
+        let repo = repo_with_tasks();
+
+        // Act
+        let result = repo.delete_todo(Todo::new(999, "Missing".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::NotFound(TodoRef::Id(999)))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        // Arrange
+        let repo = Arc::new(InMemoryTodoRepository::new());
+
+        // Act
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let repo = Arc::clone(&repo);
+                thread::spawn(move || {
+                    for todo in 0..25 {
+                        repo.create_todo(Todo::new(0, format!("worker {} todo {}", worker, todo))).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        // Assert
+        let mut ids: Vec<usize> = repo.get_todos(None).unwrap().iter().map(|todo| todo.id).collect();
+        ids.sort();
+        assert_eq!(ids, (1..=100).collect::<Vec<usize>>());
+    }
+}
@@ -1,2 +1,22 @@
 pub mod todo;
-pub mod file_todo_repository;
\ No newline at end of file
+pub mod lists;
+pub mod error;
+pub mod due;
+pub mod recurrence;
+pub mod sort;
+pub mod subtasks;
+pub mod dependencies;
+pub mod edit;
+pub mod output;
+pub mod table;
+pub mod color;
+pub mod file_todo_repository;
+pub mod in_memory_todo_repository;
+mod todo_store;
+pub mod config;
+pub mod location;
+pub mod storage;
+pub mod nudge;
+pub mod notifier;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_todo_repository;
@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashSet};
+use crate::error::NudgelystError;
+use crate::subtasks::descendants;
+use crate::todo::{Todo, TodoRepository};
+
+/// The list todos go in unless told otherwise. It always exists and can't be
+/// renamed or deleted; its todos are stored without a list name.
+pub const DEFAULT_LIST: &str = "default";
+
+/// A named list and how many todos are in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TodoList {
+    pub name: String,
+    pub todos: usize,
+    pub completed: usize,
+}
+
+/// List names are kept short and shell-friendly, e.g. `work` or `release-1.4`.
+pub fn validate_list_name(name: &str) -> Result<(), NudgelystError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|char| char.is_alphanumeric() || matches!(char, '-' | '_' | '.'));
+    if !valid {
+        return Err(NudgelystError::InvalidInput(format!(
+            "invalid list name `{}`; use letters, digits, `-`, `_` and `.`",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+/// What a todo in the list named `name` stores as its list.
+pub fn list_field(name: &str) -> Option<String> {
+    Some(name.to_string()).filter(|name| name != DEFAULT_LIST)
+}
+
+/// Moves `todo` to the list named `to` along with its subtasks, so they stay with
+/// their parent. A subtask moved on its own stops being one, as its parent stays
+/// behind. Everything moves in one go, or nothing does. Returns the subtasks that
+/// were moved.
+///
+/// Dependencies don't reach across lists, so the move is refused while anything
+/// moving depends on a todo staying behind or the other way round.
+pub fn move_with_subtasks(repo: &dyn TodoRepository, todo: Todo, to: &str) -> Result<Vec<Todo>, NudgelystError> {
+    if todo.list_name() == to {
+        return Err(NudgelystError::InvalidInput(format!("todo {} is already in {}", todo.id, to)));
+    }
+    let in_list = repo.get_todos_in_list(todo.list_name(), None)?;
+    let subtasks = descendants(&in_list, todo.id);
+    let moving: HashSet<usize> = subtasks.iter().map(|subtask| subtask.id).chain([todo.id]).collect();
+    let mut linked: Vec<usize> = in_list
+        .iter()
+        .filter(|other| !moving.contains(&other.id))
+        .filter(|other| {
+            other.depends_on.iter().any(|id| moving.contains(id))
+                || std::iter::once(&todo).chain(&subtasks).any(|moved| moved.depends_on.contains(&other.id))
+        })
+        .map(|other| other.id)
+        .collect();
+    linked.sort_unstable();
+    if !linked.is_empty() {
+        let linked: Vec<String> = linked.iter().map(|id| id.to_string()).collect();
+        return Err(NudgelystError::Conflict(format!(
+            "moving {} would split it from todo(s) {} it's linked to by dependencies; remove those with `depend --remove` first",
+            todo.id,
+            linked.join(", ")
+        )));
+    }
+
+    repo.move_todos(std::iter::once(todo).chain(subtasks.iter().cloned()).collect(), to)?;
+
+    Ok(subtasks)
+}
+
+/// Works out the lists a store holds: the default one, then the `named` ones in the
+/// order they were created, then any only known from the todos in them.
+pub(crate) fn count_lists<'a>(named: impl IntoIterator<Item = &'a String>, todos: &[Todo]) -> Vec<TodoList> {
+    let mut lists: Vec<TodoList> = std::iter::once(DEFAULT_LIST)
+        .chain(named.into_iter().map(String::as_str))
+        .map(|name| TodoList { name: name.to_string(), todos: 0, completed: 0 })
+        .collect();
+
+    for todo in todos {
+        let index = match lists.iter().position(|list| list.name == todo.list_name()) {
+            Some(index) => index,
+            None => {
+                lists.push(TodoList { name: todo.list_name().to_string(), todos: 0, completed: 0 });
+                lists.len() - 1
+            },
+        };
+        lists[index].todos += 1;
+        if todo.completed {
+            lists[index].completed += 1;
+        }
+    }
+
+    lists
+}
+
+/// Narrows a repository down to one of its lists: todos are read from, created in,
+/// changed and moved out of that list only, and any others are treated as not
+/// found. Changes to lists themselves go straight to the repository.
+pub struct ListScope<'a> {
+    repo: &'a dyn TodoRepository,
+    list: String,
+}
+
+impl<'a> ListScope<'a> {
+    /// Fails if `repo` has no list named `list`.
+    pub fn new(repo: &'a dyn TodoRepository, list: &str) -> Result<ListScope<'a>, NudgelystError> {
+        if !repo.get_lists()?.iter().any(|existing| existing.name == list) {
+            return Err(NudgelystError::no_list(list));
+        }
+
+        Ok(ListScope { repo, list: list.to_string() })
+    }
+
+    pub fn list(&self) -> &str {
+        &self.list
+    }
+
+    fn filter_todos<P>(&self, limit: Option<usize>, predicate: P) -> Result<Vec<Todo>, NudgelystError>
+    where
+        P: Fn(&Todo) -> bool,
+    {
+        Ok(self
+            .repo
+            .get_todos_in_list(&self.list, None)?
+            .into_iter()
+            .filter(|todo| predicate(todo))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    fn in_list(&self, todo: Todo) -> Todo {
+        Todo { list: list_field(&self.list), ..todo }
+    }
+
+    /// Makes sure `todos` are stored in this list and stay in it.
+    fn check_in_list(&self, todos: &[Todo]) -> Result<(), NudgelystError> {
+        let ids: HashSet<usize> = self.repo.get_todos_in_list(&self.list, None)?.iter().map(|todo| todo.id).collect();
+
+        match todos.iter().find(|todo| todo.list_name() != self.list || !ids.contains(&todo.id)) {
+            Some(todo) => Err(NudgelystError::not_found_id(todo.id)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TodoRepository for ListScope<'_> {
+    fn get_todo(&self, id: usize) -> Result<Todo, NudgelystError> {
+        Some(self.repo.get_todo(id)?)
+            .filter(|todo| todo.list_name() == self.list)
+            .ok_or_else(|| NudgelystError::not_found_id(id))
+    }
+
+    fn get_todo_by_name(&self, name: String) -> Result<Todo, NudgelystError> {
+        self.filter_todos(Some(1), |todo| todo.name == name)?
+            .pop()
+            .ok_or_else(|| NudgelystError::not_found_name(name))
+    }
+
+    fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.repo.get_todos_in_list(&self.list, limit)
+    }
+
+    fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.filter_todos(limit, |todo| todo.completed)
+    }
+
+    fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.filter_todos(limit, |todo| !todo.completed)
+    }
+
+    fn get_todos_with_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.filter_todos(limit, |todo| todo.has_tag(&tag))
+    }
+
+    fn get_todos_without_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.filter_todos(limit, |todo| !todo.has_tag(&tag))
+    }
+
+    fn get_tag_counts(&self) -> Result<BTreeMap<String, usize>, NudgelystError> {
+        let mut counts = BTreeMap::new();
+        for todo in self.get_todos(None)? {
+            for tag in todo.tags {
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+        }
+
+        Ok(counts)
+    }
+
+    fn get_todos_in_list(&self, list: &str, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.repo.get_todos_in_list(list, limit)
+    }
+
+    fn get_lists(&self) -> Result<Vec<TodoList>, NudgelystError> {
+        self.repo.get_lists()
+    }
+
+    fn create_list(&self, name: &str) -> Result<(), NudgelystError> {
+        self.repo.create_list(name)
+    }
+
+    fn rename_list(&self, from: &str, to: &str) -> Result<(), NudgelystError> {
+        self.repo.rename_list(from, to)
+    }
+
+    fn delete_list(&self, name: &str) -> Result<(), NudgelystError> {
+        self.repo.delete_list(name)
+    }
+
+    fn move_todos(&self, todos: Vec<Todo>, to: &str) -> Result<(), NudgelystError> {
+        self.check_in_list(&todos)?;
+        self.repo.move_todos(todos, to)
+    }
+
+    fn create_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.repo.create_todo(self.in_list(todo))
+    }
+
+    fn create_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.repo.create_todos(todos.into_iter().map(|todo| self.in_list(todo)).collect())
+    }
+
+    fn update_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.check_in_list(std::slice::from_ref(&todo))?;
+        self.repo.update_todo(todo)
+    }
+
+    fn update_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.check_in_list(&todos)?;
+        self.repo.update_todos(todos)
+    }
+
+    fn delete_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        self.check_in_list(std::slice::from_ref(&todo))?;
+        self.repo.delete_todo(todo)
+    }
+
+    fn delete_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        self.check_in_list(&todos)?;
+        self.repo.delete_todos(todos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::in_memory_todo_repository::InMemoryTodoRepository;
+
+    fn repo() -> InMemoryTodoRepository {
+        let repo = InMemoryTodoRepository::with_todos(vec![Todo::new(0, "Buy milk".to_string())]).unwrap();
+        repo.create_list("work").unwrap();
+        repo.create_todos(vec![
+            Todo { list: list_field("work"), ..Todo::new(0, "Ship it".to_string()) },
+            Todo { list: list_field("work"), completed: true, ..Todo::new(0, "Plan it".to_string()) },
+        ]).unwrap();
+
+        repo
+    }
+
+    #[test]
+    fn test_scope_reads_only_its_list() {
+        // Arrange
+        let repo = repo();
+        let scope = ListScope::new(&repo, "work").unwrap();
+
+        // Act
+        let todos = scope.get_todos(None).unwrap();
+        let other_list = scope.get_todo(1);
+
+        // Assert
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(matches!(other_list, Err(NudgelystError::NotFound(_))));
+        assert_eq!(scope.get_todo_by_name("Ship it".to_string()).unwrap().id, 2);
+        assert!(scope.get_todo_by_name("Buy milk".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_scope_creates_in_its_list() {
+        // Arrange
+        let repo = repo();
+        let scope = ListScope::new(&repo, "work").unwrap();
+
+        // Act
+        let result = scope.create_todo(Todo::new(0, "Review it".to_string()));
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(repo.get_todo(4).unwrap().list.as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn test_scope_changes_only_its_list() {
+        // Arrange
+        let repo = repo();
+        let scope = ListScope::new(&repo, "work").unwrap();
+        let other_list = repo.get_todo(1).unwrap();
+        let leaving = Todo { list: None, ..repo.get_todo(2).unwrap() };
+        let before = repo.get_todos(None).unwrap();
+
+        // Act
+        let updated = scope.update_todo(Todo { completed: true, ..other_list.clone() });
+        let deleted = scope.delete_todos(vec![repo.get_todo(3).unwrap(), other_list.clone()]);
+        let moved = scope.move_todos(vec![other_list], "work");
+        let left = scope.update_todo(leaving);
+
+        // Assert
+        assert!(matches!(updated, Err(NudgelystError::NotFound(_))));
+        assert!(matches!(deleted, Err(NudgelystError::NotFound(_))));
+        assert!(matches!(moved, Err(NudgelystError::NotFound(_))));
+        assert!(matches!(left, Err(NudgelystError::NotFound(_))));
+        assert_eq!(repo.get_todos(None).unwrap(), before);
+    }
+
+    #[test]
+    fn test_scope_needs_an_existing_list() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = ListScope::new(&repo, "home");
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_move_with_subtasks() {
+        // Arrange
+        let repo = repo();
+        repo.create_todo(Todo { parent_id: Some(1), ..Todo::new(0, "Find oat milk".to_string()) }).unwrap();
+        let scope = ListScope::new(&repo, DEFAULT_LIST).unwrap();
+
+        // Act
+        let result = move_with_subtasks(&scope, scope.get_todo(1).unwrap(), "work");
+
+        // Assert
+        match result {
+            Ok(subtasks) => {
+                assert_eq!(subtasks.len(), 1);
+                assert!(scope.get_todos(None).unwrap().is_empty());
+                assert_eq!(repo.get_todo(4).unwrap().list_name(), "work");
+                assert_eq!(repo.get_todo(4).unwrap().parent_id, Some(1));
+                assert_eq!(repo.get_lists().unwrap()[1], TodoList { name: "work".to_string(), todos: 4, completed: 1 });
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_move_subtask_on_its_own() {
+        // Arrange
+        let repo = repo();
+        repo.create_todo(Todo { parent_id: Some(1), ..Todo::new(0, "Find oat milk".to_string()) }).unwrap();
+
+        // Act
+        let result = move_with_subtasks(&repo, repo.get_todo(4).unwrap(), "work");
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(repo.get_todo(4).unwrap().parent_id, None);
+        assert_eq!(repo.get_todo(4).unwrap().list_name(), "work");
+    }
+
+    #[test]
+    fn test_failed_move_changes_nothing() {
+        // Arrange
+        let repo = repo();
+        repo.create_todo(Todo { parent_id: Some(1), ..Todo::new(0, "Find oat milk".to_string()) }).unwrap();
+        let before = repo.get_todo(4).unwrap();
+
+        // Act
+        let missing = move_with_subtasks(&repo, before.clone(), "nowhere");
+        let same = move_with_subtasks(&repo, before.clone(), DEFAULT_LIST);
+
+        // Assert
+        assert!(matches!(missing, Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(same, Err(NudgelystError::InvalidInput(_))));
+        assert_eq!(repo.get_todo(4).unwrap(), before);
+    }
+
+    #[test]
+    fn test_move_refused_across_dependencies() {
+        // Arrange
+        let repo = repo();
+        repo.create_todos(vec![
+            Todo::new(0, "Find a glass".to_string()),
+            Todo { depends_on: vec![1, 4], ..Todo::new(0, "Drink milk".to_string()) },
+        ]).unwrap();
+
+        // Act
+        let dependency = move_with_subtasks(&repo, repo.get_todo(1).unwrap(), "work");
+        let dependent = move_with_subtasks(&repo, repo.get_todo(5).unwrap(), "work");
+
+        // Assert
+        assert!(matches!(dependency, Err(NudgelystError::Conflict(_))));
+        assert!(matches!(dependent, Err(NudgelystError::Conflict(ref message)) if message.contains("1, 4")));
+        assert_eq!(repo.get_todos_in_list(DEFAULT_LIST, None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_validate_list_name() {
+        assert!(validate_list_name("release-1.4").is_ok());
+        assert!(validate_list_name("").is_err());
+        assert!(validate_list_name("my list").is_err());
+        assert!(validate_list_name("a/b").is_err());
+    }
+}
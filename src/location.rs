@@ -0,0 +1,232 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use crate::config::{self, Config};
+use crate::error::NudgelystError;
+use crate::storage::Backend;
+
+pub const TODO_FILE_ENV_VAR: &str = "NUDGELYST_FILE";
+
+/// Resolves where the todo list lives. In order of precedence:
+/// the `--file` argument, the `NUDGELYST_FILE` env var, the nearest project-local
+/// list (skipped when `global` is set), `storage.path` in the config file and
+/// finally `$XDG_DATA_HOME/nudgelyst/`. Lists are named after their backend,
+/// e.g. `nudgelyst.yaml`.
+pub fn resolve_todo_file(
+    file_arg: Option<PathBuf>,
+    global: bool,
+    backend: Backend,
+    config: &Config,
+) -> Result<PathBuf, NudgelystError> {
+    let env_file = env::var_os(TODO_FILE_ENV_VAR)
+        .filter(|file| !file.is_empty())
+        .map(PathBuf::from);
+    let local_file = if global {
+        None
+    } else {
+        discover_local_todo_file(&env::current_dir()?, backend.file_name())
+    };
+
+    resolve_todo_file_from(file_arg, env_file, local_file, backend, config, config::data_dir())
+}
+
+/// Walks up from `start` looking for a list named `file_name`, the same way git
+/// finds its repository. The search stops at the filesystem root or after checking
+/// a directory containing `.git`, so one project never picks up another's list.
+pub fn discover_local_todo_file(start: &Path, file_name: &str) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        let candidate = dir.join(file_name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+
+    None
+}
+
+fn resolve_todo_file_from(
+    file_arg: Option<PathBuf>,
+    env_file: Option<PathBuf>,
+    local_file: Option<PathBuf>,
+    backend: Backend,
+    config: &Config,
+    data_dir: Option<PathBuf>,
+) -> Result<PathBuf, NudgelystError> {
+    if let Some(file) = file_arg.or(env_file).or(local_file) {
+        return Ok(file);
+    }
+
+    if let Some(file) = &config.storage.path {
+        return Ok(config::expand_home(file));
+    }
+
+    data_dir
+        .map(|dir| dir.join("nudgelyst").join(backend.file_name()))
+        .ok_or_else(|| {
+            NudgelystError::InvalidInput(format!(
+                "unable to determine a data directory; pass --file or set {}",
+                TODO_FILE_ENV_VAR
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageConfig;
+    use std::fs;
+
+    fn config_with_path(path: &str) -> Config {
+        Config {
+            storage: StorageConfig { path: Some(PathBuf::from(path)), ..StorageConfig::default() },
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_file_arg_takes_precedence() {
+        // Arrange
+        let config = config_with_path("/config/todos.yaml");
+
+        // Act
+        let result = resolve_todo_file_from(
+            Some(PathBuf::from("/arg/todos.yaml")),
+            Some(PathBuf::from("/env/todos.yaml")),
+            Some(PathBuf::from("/local/nudgelyst.yaml")),
+            Backend::Yaml,
+            &config,
+            Some(PathBuf::from("/data")),
+        );
+
+        // Assert
+        assert_eq!(result.unwrap(), PathBuf::from("/arg/todos.yaml"));
+    }
+
+    #[test]
+    fn test_env_var_before_config() {
+        // Arrange
+        let config = config_with_path("/config/todos.yaml");
+
+        // Act
+        let result = resolve_todo_file_from(
+            None,
+            Some(PathBuf::from("/env/todos.yaml")),
+            Some(PathBuf::from("/local/nudgelyst.yaml")),
+            Backend::Yaml,
+            &config,
+            Some(PathBuf::from("/data")),
+        );
+
+        // Assert
+        assert_eq!(result.unwrap(), PathBuf::from("/env/todos.yaml"));
+    }
+
+    #[test]
+    fn test_local_file_before_config() {
+        // Arrange
+        let config = config_with_path("/config/todos.yaml");
+
+        // Act
+        let result = resolve_todo_file_from(
+            None,
+            None,
+            Some(PathBuf::from("/local/nudgelyst.yaml")),
+            Backend::Yaml,
+            &config,
+            Some(PathBuf::from("/data")),
+        );
+
+        // Assert
+        assert_eq!(result.unwrap(), PathBuf::from("/local/nudgelyst.yaml"));
+    }
+
+    #[test]
+    fn test_config_before_data_dir() {
+        // Arrange
+        let config = config_with_path("/config/todos.yaml");
+
+        // Act
+        let result = resolve_todo_file_from(None, None, None, Backend::Yaml, &config, Some(PathBuf::from("/data")));
+
+        // Assert
+        assert_eq!(result.unwrap(), PathBuf::from("/config/todos.yaml"));
+    }
+
+    #[test]
+    fn test_falls_back_to_data_dir() {
+        // Act
+        let result = resolve_todo_file_from(None, None, None, Backend::Yaml, &Config::default(), Some(PathBuf::from("/data")));
+
+        // Assert
+        assert_eq!(result.unwrap(), PathBuf::from("/data/nudgelyst/nudgelyst.yaml"));
+    }
+
+    #[test]
+    fn test_data_dir_file_named_after_backend() {
+        // Act
+        let result = resolve_todo_file_from(None, None, None, Backend::Sqlite, &Config::default(), Some(PathBuf::from("/data")));
+
+        // Assert
+        assert_eq!(result.unwrap(), PathBuf::from("/data/nudgelyst/nudgelyst.db"));
+    }
+
+    #[test]
+    fn test_no_data_dir() {
+        // Act
+        let result = resolve_todo_file_from(None, None, None, Backend::Yaml, &Config::default(), None);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_discover_local_todo_file_in_parent() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.path().join("nudgelyst.yaml"), "[]").unwrap();
+
+        // Act
+        let result = discover_local_todo_file(&nested, "nudgelyst.yaml");
+
+        // Assert
+        assert_eq!(result, Some(dir.path().join("nudgelyst.yaml")));
+    }
+
+    #[test]
+    fn test_discover_local_todo_file_prefers_nearest() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.path().join("nudgelyst.yaml"), "[]").unwrap();
+        fs::write(nested.join("nudgelyst.yaml"), "[]").unwrap();
+
+        // Act
+        let result = discover_local_todo_file(&nested, "nudgelyst.yaml");
+
+        // Assert
+        assert_eq!(result, Some(nested.join("nudgelyst.yaml")));
+    }
+
+    #[test]
+    fn test_discover_local_todo_file_stops_at_git_boundary() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let nested = repo.join("src");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.path().join("nudgelyst.yaml"), "[]").unwrap();
+
+        // Act
+        let result = discover_local_todo_file(&nested, "nudgelyst.yaml");
+
+        // Assert
+        assert_eq!(result, None);
+    }
+}
@@ -1,261 +1,812 @@
-mod todo;
-mod file_todo_repository;
-
-use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use chrono::{DateTime, Local};
 use clap::{Parser, Subcommand};
 use colored::Colorize;
-use todo::*;
-use file_todo_repository::*;
+use nudgelyst::color::ColorChoice;
+use nudgelyst::config::{config_file_path, project_config_file_path, set_in_file, Config, PROJECT_CONFIG_FILE};
+use nudgelyst::dependencies::{actionable, add_dependencies, check_dependencies, remove_dependencies};
+use nudgelyst::edit::{default_editor, edit_in_editor, TodoChanges};
+use nudgelyst::due::{end_of_day, parse_due, parse_period, parse_reminder_offset};
+use nudgelyst::error::NudgelystError;
+use nudgelyst::lists::{move_with_subtasks, ListScope, DEFAULT_LIST};
+use nudgelyst::location::resolve_todo_file;
+use nudgelyst::notifier;
+use nudgelyst::output::{render, OutputFormat};
+use nudgelyst::nudge::{delivered_path, Nudger, SystemClock};
+use nudgelyst::recurrence::Recurrence;
+use nudgelyst::sort::{sort_todos, SortOrder};
+use nudgelyst::subtasks::{check_parent, complete_with_subtasks, remove_with_subtasks, CompletePolicy, OrphanPolicy};
+use nudgelyst::storage::{import_into_sqlite, init_repository, open_repository, Backend};
+use nudgelyst::table::{render_table, Column, TableOptions, DEFAULT_COLUMNS};
+use nudgelyst::todo::*;
 
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  2  invalid usage or input
+  3  todo not found
+  4  conflict with the stored list (e.g. it already exists)
+  5  storage error (reading, writing or parsing the list or config)
+  6  a nudge couldn't be sent";
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
+    /// Todo list file to use (overrides NUDGELYST_FILE and the config file)
+    #[arg(short, long, global = true)]
+    file: Option<PathBuf>,
+    /// Use the global todo list even when inside a project with its own nudgelyst.yaml
+    #[arg(short, long, global = true)]
+    global: bool,
+    /// Storage backend to use (overrides storage.backend in the config file)
+    #[arg(short, long, global = true, value_enum)]
+    backend: Option<Backend>,
+    /// When to color output
+    #[arg(long, global = true, value_enum, default_value_t)]
+    color: ColorChoice,
+    /// Turn color off and spell out what it shows, e.g. [x] for done todos
+    #[arg(long, global = true)]
+    plain: bool,
+    /// Named list to use instead of the default one
+    #[arg(long, global = true)]
+    list: Option<String>,
     #[command(subcommand)]
     command: Command,
 }
 
-//TODO: we will want a 'clean' command that removes completed from the list
-//TODO: add a 'togone' command to delete a todo
-//TODO: add a 'wipe' command to delete all todos and start fresh!
+// Picks out a single todo by exactly one of its name or id. A plain comment, as clap
+// would otherwise use it as the help of every subcommand flattening it.
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct Selector {
+    #[arg(short, long)]
+    name: Option<String>,
+    #[arg(short, long)]
+    id: Option<usize>,
+}
+
+impl Selector {
+    fn find(self, todo_repo: &dyn TodoRepository) -> Result<Todo, NudgelystError> {
+        match (self.name, self.id) {
+            (_, Some(id)) => todo_repo.get_todo(id),
+            (Some(name), None) => todo_repo.get_todo_by_name(name),
+            (None, None) => Err(NudgelystError::InvalidInput("need either a --name or an --id".to_string())),
+        }
+    }
+
+    /// Like `find`, but when going by name an incomplete todo wins over a completed
+    /// one, such as the record left behind by a recurring todo.
+    fn find_incomplete(self, todo_repo: &dyn TodoRepository) -> Result<Todo, NudgelystError> {
+        if let Some(name) = &self.name {
+            let incomplete = todo_repo.get_incomplete_todos(None)?;
+            if let Some(todo) = incomplete.into_iter().find(|todo| &todo.name == name) {
+                return Ok(todo);
+            }
+        }
+
+        self.find(todo_repo)
+    }
+}
 
 #[derive(Subcommand, Debug)]
 #[clap(rename_all = "snake_case")]
 pub enum Command {
+    /// Create a project-local todo list in the current directory
+    Init {
+    },
+    /// Import an existing YAML todo list into the SQLite backend
+    Migrate {
+        /// YAML list to import; defaults to the list nudgelyst would otherwise use
+        #[arg(long)]
+        from: Option<PathBuf>,
+    },
     Todo {
+        /// What to do; words like "+work" become tags rather than part of the name
         name: String,
-    },
-    Todone {
+        /// When the todo is due, e.g. "tomorrow 5pm", "fri", "in 3 days" or "2024-05-01"
         #[arg(short, long)]
-        name: Option<String>,
+        due: Option<String>,
+        /// Also nudge this long before it's due, e.g. "1h" or "2 days"; can be repeated
+        #[arg(short, long, requires = "due")]
+        remind: Vec<String>,
+        /// Bring the todo back after it's done, e.g. "daily", "every 2 weeks", "mon,thu" or an RRULE
+        #[arg(long)]
+        repeat: Option<String>,
+        #[arg(short, long, value_enum, default_value_t)]
+        priority: Priority,
+        /// Make it a subtask of the todo with this id
         #[arg(short, long)]
-        id: Option<usize>,
+        under: Option<usize>,
+        /// How to print the todos listed afterwards
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Mark a todo as done
+    Todone {
+        #[command(flatten)]
+        selector: Selector,
+        /// What to do if it has open subtasks (overrides subtasks.on_complete in the config file)
+        #[arg(long, value_enum)]
+        children: Option<CompletePolicy>,
     },
+    /// Mark a done todo as not done again
     Tundo {
-        #[arg(short, long)]
-        name: Option<String>,
-        #[arg(short, long)]
-        id: Option<usize>,
+        #[command(flatten)]
+        selector: Selector,
     },
+    /// List todos, or create, rename or delete a named list
+    #[command(args_conflicts_with_subcommands = true)]
     List {
+        #[command(subcommand)]
+        action: Option<ListAction>,
         #[arg(short, long)]
         completed: Option<bool>,
         #[arg(short, long)]
         incomplete: Option<bool>,
+        /// Show at most this many (overrides display.limit in the config file)
         #[arg(short, long)]
         limit: Option<usize>,
+        /// Order to show todos in (overrides display.sort in the config file)
+        #[arg(short, long, value_enum)]
+        sort: Option<SortOrder>,
+        /// Only show todos with this tag
+        #[arg(short, long)]
+        tag: Option<String>,
+        /// Only show todos without this tag
+        #[arg(short, long = "without-tag")]
+        without_tag: Option<String>,
+        /// Only show todos created (or see --by) since then, e.g. "yesterday", "mon" or "3 days ago"
+        #[arg(long)]
+        since: Option<String>,
+        /// Only show todos created (or see --by) before the end of then, e.g. "yesterday" or "2024-05-01"
+        #[arg(long)]
+        until: Option<String>,
+        /// Which timestamp --since and --until look at
+        #[arg(long, value_enum, default_value_t)]
+        by: TimestampKind,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+        /// Columns the table shows, e.g. "id,name,due" (overrides table.columns in the config file)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Vec<Column>,
     },
+    /// Delete a todo
     Togone {
+        #[command(flatten)]
+        selector: Selector,
+        /// What to do with its subtasks, if it has any
+        #[arg(long, value_enum, default_value_t)]
+        children: OrphanPolicy,
+    },
+    /// Add a timestamped note to a todo
+    Annotate {
+        #[command(flatten)]
+        selector: Selector,
+        note: String,
+    },
+    /// Make a todo wait on other todos being done first
+    Depend {
+        #[command(flatten)]
+        selector: Selector,
+        /// Id of a todo this one depends on; can be repeated
+        #[arg(short, long, required = true)]
+        on: Vec<usize>,
+        /// Remove these dependencies instead of adding them
+        #[arg(long)]
+        remove: bool,
+    },
+    /// List the incomplete todos that aren't waiting on anything
+    Next {
+        /// Show at most this many (overrides display.limit in the config file)
+        #[arg(short, long)]
+        limit: Option<usize>,
+        /// Order to show todos in (overrides display.sort in the config file)
+        #[arg(short, long, value_enum)]
+        sort: Option<SortOrder>,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+        /// Columns the table shows, e.g. "id,name,due" (overrides table.columns in the config file)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Vec<Column>,
+    },
+    /// Show every list with how many todos are in it
+    Lists {
+    },
+    /// Move a todo, along with its subtasks, to another list
+    Move {
+        #[command(flatten)]
+        selector: Selector,
+        /// List to move it to
+        #[arg(short, long)]
+        to: String,
+    },
+    /// Print everything about a todo, notes included
+    Show {
+        #[command(flatten)]
+        selector: Selector,
+    },
+    /// Change a todo from flags, or open it in $EDITOR when no flags are given
+    Edit {
+        #[command(flatten)]
+        selector: Selector,
+        /// New name for the todo
+        #[arg(long)]
+        rename: Option<String>,
+        /// New due date, e.g. "tomorrow 5pm"
+        #[arg(short, long, conflicts_with = "no_due")]
+        due: Option<String>,
+        /// Remove the due date and its reminders
+        #[arg(long = "no-due")]
+        no_due: bool,
+        /// Replace the reminders, e.g. "1h"; can be repeated
+        #[arg(short, long)]
+        remind: Vec<String>,
+        /// New recurrence, e.g. "every 2 weeks"
+        #[arg(long, conflicts_with = "no_repeat")]
+        repeat: Option<String>,
+        /// Stop the todo from coming back
+        #[arg(long = "no-repeat")]
+        no_repeat: bool,
+        #[arg(short, long, value_enum)]
+        priority: Option<Priority>,
+        /// Add a tag; can be repeated
         #[arg(short, long)]
-        name: Option<String>,
+        tag: Vec<String>,
+        /// Remove a tag; can be repeated
         #[arg(short, long)]
-        id: Option<usize>,
+        untag: Vec<String>,
     },
+    /// Delete completed todos
     Clean {
+        /// Only delete completed todos with this tag
+        #[arg(short, long)]
+        tag: Option<String>,
+    },
+    /// Show every tag in use and how many todos carry it
+    Tags {
+    },
+    /// Keep running and nudge about todos as their reminders fall due
+    Nudge {
+    },
+    /// Show or change settings
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
     },
     Wipe {
     }
 }
-fn main() {
-    let file_repo = FileTodoRepository;
 
+#[derive(Subcommand, Debug)]
+pub enum ListAction {
+    /// Start a new, empty list
+    Create {
+        name: String,
+    },
+    /// Rename a list, keeping its todos
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Delete a list
+    Delete {
+        name: String,
+        /// Delete it even if it still has todos, deleting them too
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print every setting with its value, defaults included
+    List {
+    },
+    /// Print the value of a setting, e.g. "display.sort"
+    Get {
+        key: String,
+    },
+    /// Change a setting in the user config file, e.g. "theme.done blue"
+    Set {
+        key: String,
+        value: String,
+        /// Change it for the current project instead, in its nudgelyst.toml
+        #[arg(long)]
+        project: bool,
+    },
+}
+
+fn main() -> ExitCode {
     let args = Args::parse();
 
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), NudgelystError> {
+    if args.plain { ColorChoice::Never } else { args.color }.apply();
+    if let Command::Config { action } = args.command {
+        // handled before the config is loaded, so a broken config file can still be fixed
+        return run_config_command(action, args.global);
+    }
+    let config = Config::load(args.global)?;
+    let backend = args.backend.or(config.storage.backend).unwrap_or_default();
+    let todo_file = || resolve_todo_file(args.file.clone(), args.global, backend, &config);
+
     match args.command {
-        Command::Todo { name } => {
-            if let Err(err) = file_repo.create_todo(Todo::new(0, name)) {
-                eprintln!("error writing to nudgelyst.yaml file: Error: {}", err);
+        Command::Init {  } => {
+            let path = args.file.clone().unwrap_or_else(|| PathBuf::from(backend.file_name()));
+            init_repository(backend, &path)?;
+            println!("Created {} - nudge away!", path.display());
+        },
+        Command::Migrate { from } => {
+            if backend != Backend::Sqlite {
+                return Err(NudgelystError::InvalidInput(
+                    "migrate imports a YAML list into SQLite; pass --backend sqlite or set storage.backend in the config file".to_string(),
+                ));
             }
 
-            if let Err(err) = list_last_five(file_repo) {
-                eprintln!("error printing last 5: Error: {}", err);
+            let from = match from {
+                Some(from) => from,
+                None => resolve_todo_file(None, args.global, Backend::Yaml, &config)?,
+            };
+            let todo_file = todo_file()?;
+            let imported = import_into_sqlite(&from, &todo_file)?;
+            println!("Migrated {} todos from {} to {}", imported, from.display(), todo_file.display());
+        },
+        Command::Nudge {  } => {
+            let todo_file = todo_file()?;
+            let todo_repo = open_repository(backend, todo_file.clone())?;
+            // without --list, nudges come from every list
+            let scope = args.list.as_deref().map(|list| ListScope::new(todo_repo.as_ref(), list)).transpose()?;
+            let nudge_repo: &dyn TodoRepository = match &scope {
+                Some(scope) => scope,
+                None => todo_repo.as_ref(),
+            };
+            let notifier = notifier::from_config(config.notify_for(&todo_file), &config.display.date_format)?;
+            let nudger = Nudger::new(nudge_repo, SystemClock, delivered_path(&todo_file, args.list.as_deref()));
+
+            println!("Nudging about {} - press Ctrl-C to stop", todo_file.display());
+            nudger.run(
+                |reminder| notifier.notify(reminder),
+                |err| eprintln!("error: {}", err),
+            );
+        },
+        Command::Lists {  } => {
+            let todo_repo = open_repository(backend, todo_file()?)?;
+            for list in todo_repo.get_lists()? {
+                println!("{}: {} todos, {} done", list.name, list.todos, list.completed);
             }
         },
-        Command::Todone { name, id } => match (name, id) {
-            (Some(todo_name), None) => {
-                if let Some(todo) = file_repo.get_todo_by_name(todo_name.clone()).ok().map(|t| t) {
-                    if let Err(err) = file_repo.update_todo(Todo { id: todo.id, name: todo.name.clone(), completed: true }) {
-                        eprintln!("error marking '{}' todo completed: Error: {}", todo_name, err);
-                    }
-                } else {
-                    eprintln!("Todo with name '{}' not found.", todo_name);
-                }
+        Command::List { action: Some(action), .. } => {
+            let todo_repo = open_repository(backend, todo_file()?)?;
+            run_list_command(todo_repo.as_ref(), action)?;
+        },
+        command => {
+            let todo_repo = open_repository(backend, todo_file()?)?;
+            let list = ListScope::new(todo_repo.as_ref(), args.list.as_deref().unwrap_or(DEFAULT_LIST))?;
+            run_todo_command(&list, &config, args.plain, command)?;
+        },
+    }
 
+    Ok(())
+}
 
-            },
-            (None, Some(todo_id)) => {
-                if let Some(todo) = file_repo.get_todo(todo_id).ok().map(|t| t) {
-                    if let Err(err) = file_repo.update_todo(Todo { id: todo.id, name: todo.name.clone(), completed: true }) {
-                        eprintln!("error marking '{}' todo completed: Error: {}", todo_id, err);
-                    }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_id);
-                }
-            },
-            (Some(_), Some(todo_id)) => {
-                if let Some(todo) = file_repo.get_todo(todo_id).ok().map(|t| t) {
-                    if let Err(err) = file_repo.update_todo(Todo { id: todo.id, name: todo.name.clone(), completed: true }) {
-                        eprintln!("error marking '{}' todo completed: Error: {}", todo_id, err);
-                    }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_id);
-                }
-            },
-            (None, None) => panic!("Need either a name or id argument to complete a task"),
+fn run_todo_command(todo_repo: &dyn TodoRepository, config: &Config, plain: bool, command: Command) -> Result<(), NudgelystError> {
+    match command {
+        Command::Init {  } | Command::Migrate { .. } | Command::Nudge {  } | Command::Config { .. } | Command::Lists {  }
+        | Command::List { action: Some(_), .. } => {
+            unreachable!("handled before the todo list is opened")
         },
-        Command::Tundo { name, id } => match (name, id) {
-            (Some(todo_name), None) => {
-                if let Some(todo) = file_repo.get_todo_by_name(todo_name.clone()).ok().map(|t| t) {
-                    if let Err(err) = file_repo.update_todo(Todo { id: todo.id, name: todo.name.clone(), completed: false }) {
-                        eprintln!("error marking '{}' todo completed: Error: {}", todo_name, err);
-                    }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_name);
-                }
+        Command::Todo { name, due, remind, repeat, priority, under, format } => {
+            let (name, tags) = split_tags(&name);
+            if name.is_empty() {
+                return Err(NudgelystError::InvalidInput("a todo needs a name as well as tags".to_string()));
             }
-            (None, Some(todo_id)) => {
-                if let Some(todo) = file_repo.get_todo(todo_id).ok().map(|t| t) {
-                    if let Err(err) = file_repo.update_todo(Todo { id: todo.id, name: todo.name.clone(), completed: false }) {
-                        eprintln!("error marking '{}' todo completed: Error: {}", todo_id, err);
-                    }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_id);
-                }
+            check_name(&name)?;
+            let now = Local::now();
+            let mut due = due.map(|due| parse_due(&due, now)).transpose()?;
+            let remind_before = parse_reminders(&remind)?;
+            let recurrence = repeat.map(|rule| rule.parse::<Recurrence>()).transpose()?;
+            if let (None, Some(recurrence)) = (due, &recurrence) {
+                // a chore without a due date is due by the end of the first day it falls on
+                due = end_of_day(recurrence.first_on_or_after(now.date_naive()));
             }
-            (Some(_), Some(todo_id)) => {
-                if let Some(todo) = file_repo.get_todo(todo_id).ok().map(|t| t) {
-                    if let Err(err) = file_repo.update_todo(Todo { id: todo.id, name: todo.name.clone(), completed: false }) {
-                        eprintln!("error marking '{}' todo completed: Error: {}", todo_id, err);
-                    }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_id);
-                }
+
+            if let Some(parent_id) = under {
+                todo_repo.get_todo(parent_id)?;
             }
-            (None, None) => panic!("Need either a name or id argument to incomplete a task"),
+
+            todo_repo.create_todo(Todo { due, remind_before, recurrence, priority, tags, parent_id: under, ..Todo::new(0, name) })?;
+
+            list_recent(todo_repo, config.display.recent, format, &table_options(config, Vec::new(), plain))?;
         },
-        Command::List {completed, incomplete, limit} => {
-            if let Err(err) = list_all_todos(file_repo, completed, incomplete, limit) {
-                eprintln!("error printing all todos: Error: {}", err)
+        Command::Todone { selector, children } => {
+            let todo = selector.find_incomplete(todo_repo)?;
+            let policy = children.unwrap_or(config.subtasks.on_complete);
+            let completion = complete_with_subtasks(todo_repo, todo, policy, Local::now())?;
+            if !completion.also_completed.is_empty() {
+                println!("Also completed {} subtask(s)", completion.also_completed.len());
+            }
+            if !completion.left_open.is_empty() {
+                println!("{} {} subtask(s) are still open", "Heads up:".yellow().bold(), completion.left_open.len());
             }
+            if let Some(due) = completion.next.and_then(|next| next.due) {
+                println!("Done! It's back again {}", due.format(&config.display.date_format));
+            }
+        },
+        Command::Tundo { selector } => {
+            let todo = selector.find(todo_repo)?;
+            todo_repo.update_todo(Todo { completed: false, ..todo })?;
+        },
+        Command::List {action: None, completed, incomplete, limit, sort, tag, without_tag, since, until, by, format, columns} => {
+            let now = Local::now();
+            let filter = ListFilter {
+                completed: wanted_completion(completed, incomplete),
+                tag,
+                without_tag,
+                since: since.map(|since| parse_period(&since, now)).transpose()?.map(|(start, _)| start),
+                until: until.map(|until| parse_period(&until, now)).transpose()?.map(|(_, end)| end),
+                by,
+            };
+            let limit = limit.or(config.display.limit);
+            let sort = sort.unwrap_or(config.display.sort);
+            list_all_todos(todo_repo, filter, limit, sort, format, &table_options(config, columns, plain))?;
         },
-        Command::Togone { name, id } => match ( name, id ) {
-            (Some(todo_name), None) => {
-                if let Some(todo) = file_repo.get_todo_by_name(todo_name.clone()).ok().map(|t| t) {
-                    if let Err(err) = file_repo.delete_todo(Todo { id: todo.id, name: todo.name.clone(), completed: false }) {
-                        eprintln!("error deleting '{}': Error: {}", todo_name, err);
+        Command::Edit { selector, rename, due, no_due, remind, repeat, no_repeat, priority, tag, untag } => {
+            let now = Local::now();
+            let changes = TodoChanges {
+                name: rename,
+                due: match (due, no_due) {
+                    (Some(due), _) => Some(Some(parse_due(&due, now)?)),
+                    (None, true) => Some(None),
+                    (None, false) => None,
+                },
+                remind_before: Some(parse_reminders(&remind)?).filter(|remind_before| !remind_before.is_empty()),
+                recurrence: match (repeat, no_repeat) {
+                    (Some(rule), _) => Some(Some(rule.parse::<Recurrence>()?)),
+                    (None, true) => Some(None),
+                    (None, false) => None,
+                },
+                priority,
+                add_tags: tag,
+                remove_tags: untag,
+            };
+
+            let todo = selector.find(todo_repo)?;
+            let id = todo.id;
+            let todo_parent_id = todo.parent_id;
+            let todo_depends_on = todo.depends_on.clone();
+            let edited = if changes.is_empty() {
+                edit_in_editor(&todo, &default_editor())?
+            } else {
+                Some(changes.apply(todo)?)
+            };
+
+            match edited {
+                Some(edited) => {
+                    if let Some(parent_id) = edited.parent_id.filter(|parent_id| Some(*parent_id) != todo_parent_id) {
+                        check_parent(todo_repo, id, parent_id)?;
                     }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_name);
-                }
-            }
-            (None, Some(todo_id)) => {
-                if let Some(todo) = file_repo.get_todo(todo_id).ok().map(|t| t) {
-                    if let Err(err) = file_repo.delete_todo(Todo { id: todo.id, name: todo.name.clone(), completed: false }) {
-                        eprintln!("error deleting '{}': Error: {}", todo_id, err);
+                    if edited.depends_on != todo_depends_on {
+                        check_dependencies(todo_repo, &edited)?;
                     }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_id);
-                }
+                    todo_repo.update_todo(edited)?;
+                    list_todos_in_term(todo_repo, vec![todo_repo.get_todo(id)?], &table_options(config, Vec::new(), plain))?;
+                },
+                None => println!("No changes made"),
             }
-            (Some(_), Some(todo_id)) => {
-                if let Some(todo) = file_repo.get_todo(todo_id).ok().map(|t| t) {
-                    if let Err(err) = file_repo.delete_todo(Todo { id: todo.id, name: todo.name.clone(), completed: false }) {
-                        eprintln!("error deleting '{}': Error: {}", todo_id, err);
-                    }
-                } else {
-                    eprintln!("Todo with id '{}' not found.", todo_id);
-                }
+        },
+        Command::Annotate { selector, note } => {
+            if note.trim().is_empty() {
+                return Err(NudgelystError::InvalidInput("the note is empty".to_string()));
             }
-            (None, None) => panic!("Need either a name or id argument to delete a task"),
+            let mut todo = selector.find(todo_repo)?;
+            todo.annotate(&note, Local::now());
+            todo_repo.update_todo(todo)?;
         },
-        Command::Clean {  } => {
-            if let Ok(existing_completed_todos) = file_repo.get_completed_todos(None) {
-                if let Err(err) = file_repo.delete_todos(existing_completed_todos) {
-                    eprintln!("error cleaning the nudgelyst.yaml file: Error: {}", err)
-                }
+        Command::Depend { selector, on, remove } => {
+            let todo = selector.find(todo_repo)?;
+            let id = todo.id;
+            if remove {
+                remove_dependencies(todo_repo, todo, &on)?;
             } else {
-                eprintln!("error cleaning the nudgelyst.yaml file")
+                add_dependencies(todo_repo, todo, &on)?;
+            }
+
+            list_todos_in_term(todo_repo, vec![todo_repo.get_todo(id)?], &table_options(config, Vec::new(), plain))?;
+        },
+        Command::Next { limit, sort, format, columns } => {
+            let mut todos = actionable(todo_repo.get_todos(None)?);
+            sort_todos(&mut todos, sort.unwrap_or(config.display.sort), Local::now());
+            todos.truncate(limit.or(config.display.limit).unwrap_or(usize::MAX));
+
+            print_todos(todo_repo, todos, format, &table_options(config, columns, plain))?;
+        },
+        Command::Move { selector, to } => {
+            let todo = selector.find(todo_repo)?;
+            let name = todo.name.clone();
+            let subtasks = move_with_subtasks(todo_repo, todo, &to)?;
+            match subtasks.len() {
+                0 => println!("Moved {} to {}", name, to),
+                count => println!("Moved {} and {} subtask(s) to {}", name, count, to),
             }
+        },
+        Command::Show { selector } => {
+            let todo = selector.find(todo_repo)?;
+            show_todo_in_term(todo_repo, config, &todo)?;
+        },
+        Command::Togone { selector, children } => {
+            let todo = selector.find(todo_repo)?;
+            remove_with_subtasks(todo_repo, vec![todo], children)?;
+        },
+        Command::Clean { tag } => {
+            let existing_completed_todos = match tag {
+                Some(tag) => todo_repo.get_todos_with_tag(tag, None)?.into_iter().filter(|todo| todo.completed).collect(),
+                None => todo_repo.get_completed_todos(None)?,
+            };
+            // subtasks still around move up to the nearest ancestor that is staying
+            remove_with_subtasks(todo_repo, existing_completed_todos, OrphanPolicy::Promote)?;
 
             println!("Wow you got a lot done!")
         },
-        Command::Wipe {  } => {
-            if let Ok(existing_todos) = file_repo.get_todos(None) {
-                if let Err(err) = file_repo.delete_todos(existing_todos) {
-                    eprintln!("error wiping the nudgelyst.yaml file - consider deleting: Error: {}", err)
-                }
-            } else {
-                eprintln!("error wiping the nudgelyst.yaml file - consider deleting")
+        Command::Tags {  } => {
+            let tag_counts = todo_repo.get_tag_counts()?;
+            if tag_counts.is_empty() {
+                println!("No tags yet - add some with +tag when creating a todo");
+            }
+            for (tag, count) in tag_counts {
+                println!("+{}: {}", tag, count);
             }
+        },
+        Command::Wipe {  } => {
+            let existing_todos = todo_repo.get_todos(None)?;
+            todo_repo.delete_todos(existing_todos)?;
 
             println!("Slate wiped! Time to get some stuff 'todone'!")
         }
     }
-}
-
-fn list_last_five(todo_repo: FileTodoRepository) -> Result<(), Box<dyn Error>> {
-    let todos = todo_repo.get_todos(Some(5))?;
-
-    list_todos_in_term(todos);
 
     Ok(())
 }
 
-fn list_all_todos(todo_repo: FileTodoRepository, completed_flag: Option<bool>, incomplete_flag: Option<bool>, limit: Option<usize>)
-    -> Result<(), Box<dyn Error>> {
-    let todos = match (completed_flag, incomplete_flag) {
-        (Some(true), None) => {
-            todo_repo.get_completed_todos(limit)?
-        },
-        (Some(false), None) => {
-            todo_repo.get_todos(limit)?
+fn parse_reminders(offsets: &[String]) -> Result<Vec<u64>, NudgelystError> {
+    offsets.iter().map(|offset| parse_reminder_offset(offset)).collect()
+}
+
+fn run_list_command(todo_repo: &dyn TodoRepository, action: ListAction) -> Result<(), NudgelystError> {
+    match action {
+        ListAction::Create { name } => {
+            todo_repo.create_list(&name)?;
+            println!("Created {} - add to it with --list {}", name, name);
         },
-        (Some(true), Some(false)) => {
-            todo_repo.get_completed_todos(limit)?
+        ListAction::Rename { name, new_name } => {
+            todo_repo.rename_list(&name, &new_name)?;
+            println!("Renamed {} to {}", name, new_name);
         },
-        (Some(false), Some(true)) => {
-            todo_repo.get_incomplete_todos(limit)?
+        ListAction::Delete { name, force } => {
+            let todos = todo_repo.get_todos_in_list(&name, None)?.len();
+            // the default list is left for delete_list to refuse
+            if todos > 0 && !force && name != DEFAULT_LIST {
+                return Err(NudgelystError::Conflict(format!(
+                    "{} still has {} todo(s); pass --force to delete them with it",
+                    name, todos
+                )));
+            }
+            todo_repo.delete_list(&name)?;
+            println!("Deleted {}", name);
         },
-        (None, Some(true)) => {
-            todo_repo.get_incomplete_todos(limit)?
+    }
+
+    Ok(())
+}
+
+fn run_config_command(action: ConfigAction, global: bool) -> Result<(), NudgelystError> {
+    match action {
+        ConfigAction::List {  } => {
+            for (key, value) in Config::load(global)?.entries()? {
+                println!("{} = {}", key, value);
+            }
         },
-        (None, Some(false)) => {
-            todo_repo.get_todos(limit)?
+        ConfigAction::Get { key } => match Config::load(global)?.get(&key)? {
+            toml::Value::String(value) => println!("{}", value),
+            value => println!("{}", value),
         },
-        (None, None) => {
-            todo_repo.get_todos(limit)?
+        ConfigAction::Set { key, value, project } => {
+            let path = if project {
+                let current_dir = std::env::current_dir()?;
+                project_config_file_path(&current_dir).unwrap_or_else(|| current_dir.join(PROJECT_CONFIG_FILE))
+            } else {
+                config_file_path().ok_or_else(|| {
+                    NudgelystError::InvalidInput("unable to determine a config directory; set HOME or XDG_CONFIG_HOME".to_string())
+                })?
+            };
+            set_in_file(&path, &key, &value)?;
         },
-        (Some(false), Some(false)) => {
-            todo_repo.get_todos(limit)?
+    }
+
+    Ok(())
+}
+
+/// Lists the `count` most recently added todos.
+fn list_recent(todo_repo: &dyn TodoRepository, count: usize, format: OutputFormat, table: &TableOptions) -> Result<(), NudgelystError> {
+    let mut todos = todo_repo.get_todos(None)?;
+    // ids only ever go up, so the newest todos have the highest
+    todos.sort_by_key(|todo| todo.id);
+    let todos = todos.split_off(todos.len().saturating_sub(count));
+
+    print_todos(todo_repo, todos, format, table)?;
+
+    Ok(())
+}
+
+/// Turns the `--completed`/`--incomplete` flags into whether listed todos should be
+/// completed, or `None` to list both.
+fn wanted_completion(completed_flag: Option<bool>, incomplete_flag: Option<bool>) -> Option<bool> {
+    match (completed_flag, incomplete_flag) {
+        (Some(true), None) | (Some(true), Some(false)) => Some(true),
+        (None, Some(true)) | (Some(false), Some(true)) => Some(false),
+        _ => None,
+    }
+}
+
+/// Which todos `list` shows.
+struct ListFilter {
+    completed: Option<bool>,
+    tag: Option<String>,
+    without_tag: Option<String>,
+    since: Option<DateTime<Local>>,
+    /// Exclusive.
+    until: Option<DateTime<Local>>,
+    by: TimestampKind,
+}
+
+impl ListFilter {
+    fn matches(&self, todo: &Todo) -> bool {
+        let timestamp = todo.timestamp(self.by);
+        let in_period = match (self.since, self.until) {
+            (None, None) => true,
+            (since, until) => timestamp.is_some_and(|timestamp| {
+                since.is_none_or(|since| timestamp >= since) && until.is_none_or(|until| timestamp < until)
+            }),
+        };
+
+        in_period
+            && self.completed.is_none_or(|completed| todo.completed == completed)
+            && self.without_tag.as_ref().is_none_or(|tag| !todo.has_tag(tag))
+    }
+}
+
+fn list_all_todos(todo_repo: &dyn TodoRepository, filter: ListFilter, limit: Option<usize>, sort: SortOrder, format: OutputFormat, table: &TableOptions)
+    -> Result<(), NudgelystError> {
+    // sorted before the limit is applied, so the limit keeps the todos that sort first
+    let mut todos = match (filter.tag.clone(), &filter.without_tag, filter.completed) {
+        (Some(tag), _, _) => todo_repo.get_todos_with_tag(tag, None)?,
+        (None, Some(without_tag), _) => todo_repo.get_todos_without_tag(without_tag.clone(), None)?,
+        (None, None, Some(true)) => todo_repo.get_completed_todos(None)?,
+        (None, None, Some(false)) => todo_repo.get_incomplete_todos(None)?,
+        (None, None, None) => todo_repo.get_todos(None)?,
+    };
+    todos.retain(|todo| filter.matches(todo));
+
+    sort_todos(&mut todos, sort, Local::now());
+    todos.truncate(limit.unwrap_or(usize::MAX));
+
+    print_todos(todo_repo, todos, format, table)
+}
+
+fn print_todos(todo_repo: &dyn TodoRepository, todos: Vec<Todo>, format: OutputFormat, table: &TableOptions) -> Result<(), NudgelystError> {
+    match format {
+        OutputFormat::Table => list_todos_in_term(todo_repo, todos, table),
+        format => {
+            print!("{}", render(&todos, format, &table.date_format)?);
+            Ok(())
         },
-        (Some(true), Some(true)) => {
-            todo_repo.get_todos(limit)?
-        }
+    }
+}
+
+/// How tables are drawn: the `--columns` asked for, else the configured ones, else
+/// the defaults. Only the defaults drop columns that are empty. `--plain` adds markers.
+fn table_options(config: &Config, columns: Vec<Column>, plain: bool) -> TableOptions {
+    let options = TableOptions {
+        markers: plain,
+        theme: config.theme.clone(),
+        date_format: config.display.date_format.clone(),
+        ..TableOptions::default()
     };
 
-    list_todos_in_term(todos);
+    match (columns.is_empty(), &config.table.columns) {
+        (false, _) => TableOptions { columns, hide_empty: false, ..options },
+        (true, Some(columns)) => TableOptions { columns: columns.clone(), hide_empty: false, ..options },
+        (true, None) => TableOptions { columns: DEFAULT_COLUMNS.to_vec(), ..options },
+    }
+}
+
+/// Prints `todos` as a table, with subtasks indented under their parents and in the
+/// order given otherwise.
+fn list_todos_in_term(todo_repo: &dyn TodoRepository, todos: Vec<Todo>, table: &TableOptions) -> Result<(), NudgelystError> {
+    if todos.is_empty() {
+        return Ok(());
+    }
+    let all = todo_repo.get_todos(None)?;
+
+    print!("{}", render_table(todos, &all, table));
 
     Ok(())
 }
 
-fn list_todos_in_term(todos: Vec<Todo>) {
-    for todo in todos {
-        if todo.completed {
-            let id_title = format!("{}", "ID: ".green());
-            let name_title = format!("{}", ", Name: ".green());
-            let colored_id = format!("{}", todo.id.to_string().green());
-            let colored_name = format!("{}", todo.name.clone().green());
-            let colored_status = "Complete!".green();
-
-            println!("{}{}{}{} - {}", id_title, colored_id, name_title, colored_name, colored_status);
-        } else {
-            let id_title = format!("{}", "ID: ".yellow());
-            let name_title = format!("{}", ", Name: ".yellow());
-            let colored_id = format!("{}", todo.id.to_string().yellow());
-            let colored_name = format!("{}", todo.name.clone().yellow());
-            let colored_status = "Incomplete".yellow();
-
-            println!("{}{}{}{} - {}", id_title, colored_id, name_title, colored_name, colored_status);
+fn show_todo_in_term(todo_repo: &dyn TodoRepository, config: &Config, todo: &Todo) -> Result<(), NudgelystError> {
+    let date_format = config.display.date_format.as_str();
+    let now = Local::now();
+    let title = |title: &str| format!("{}:", title).bold();
+
+    println!("{} {}", title("ID"), todo.id);
+    println!("{} {}", title("Name"), todo.name);
+    match (todo.completed, todo.is_overdue(now)) {
+        (true, _) => println!("{} {}", title("Status"), "Complete!".color(config.theme.done.color())),
+        (false, true) => println!("{} {}", title("Status"), "OVERDUE".color(config.theme.overdue.color()).bold()),
+        (false, false) => println!("{} Incomplete", title("Status")),
+    }
+    println!("{} {}", title("List"), todo.list_name());
+    println!("{} {}", title("Priority"), todo.priority);
+    if let Some(due) = todo.due {
+        println!("{} {}", title("Due"), due.format(date_format));
+    }
+    if !todo.remind_before.is_empty() {
+        let reminders: Vec<String> = todo
+            .remind_before
+            .iter()
+            .map(|minutes| humantime::format_duration(std::time::Duration::from_secs(minutes * 60)).to_string())
+            .collect();
+        println!("{} {} before", title("Reminders"), reminders.join(", "));
+    }
+    if let Some(recurrence) = &todo.recurrence {
+        println!("{} {}", title("Repeats"), recurrence.describe());
+    }
+    if !todo.tags.is_empty() {
+        let tags: Vec<String> = todo.tags.iter().map(|tag| format!("+{}", tag)).collect();
+        println!("{} {}", title("Tags"), tags.join(" "));
+    }
+    let all = todo_repo.get_todos(None)?;
+    for (label, timestamp) in [("Created", todo.created_at), ("Updated", todo.updated_at), ("Completed", todo.completed_at)] {
+        if let Some(timestamp) = timestamp {
+            println!("{} {}", title(label), timestamp.format(date_format));
         }
     }
-}
\ No newline at end of file
+    if let Some(parent_id) = todo.parent_id {
+        println!("{} {} ({})", title("Subtask of"), parent_id, todo_repo.get_todo(parent_id)?.name);
+    }
+    if !todo.depends_on.is_empty() {
+        println!("{}", title("Depends on"));
+        for dependency in all.iter().filter(|other| todo.depends_on.contains(&other.id)) {
+            let check = if dependency.completed { "x" } else { " " };
+            println!("  [{}] {}: {}", check, dependency.id, dependency.name);
+        }
+    }
+    let subtasks: Vec<Todo> = all
+        .into_iter()
+        .filter(|subtask| subtask.parent_id == Some(todo.id))
+        .collect();
+    if !subtasks.is_empty() {
+        let done = subtasks.iter().filter(|subtask| subtask.completed).count();
+        println!("{} {}/{} done", title("Subtasks"), done, subtasks.len());
+        for subtask in subtasks {
+            let check = if subtask.completed { "x" } else { " " };
+            println!("  [{}] {}: {}", check, subtask.id, subtask.name);
+        }
+    }
+    if !todo.notes.is_empty() {
+        println!("{}", title("Notes"));
+        for line in todo.notes.lines() {
+            println!("  {}", line);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,454 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use crate::config::NotifyConfig;
+use crate::error::NudgelystError;
+use crate::nudge::Reminder;
+
+#[cfg(not(feature = "desktop"))]
+const NO_DESKTOP_SUPPORT: &str = "nudgelyst was built without desktop notifications; rebuild it with `--features desktop`";
+
+/// How long a webhook gets to answer before the nudge counts as failed.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Gets a nudge to the user.
+pub trait Notifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), NudgelystError>;
+}
+
+/// The built-in ways of sending nudges, as named in the config file.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifierKind {
+    /// Print to the terminal nudgelyst is running in
+    Terminal,
+    /// A freedesktop notification over D-Bus (requires the `desktop` feature)
+    Desktop,
+    /// POST the reminder as JSON to `webhook_url`
+    Webhook,
+    /// Run `command` with the reminder in `NUDGELYST_*` environment variables
+    Command,
+}
+
+/// Builds the notifier described by a `[notify]` config section, writing due
+/// dates in nudges with the strftime `date_format`.
+pub fn from_config(config: &NotifyConfig, date_format: &str) -> Result<Box<dyn Notifier>, NudgelystError> {
+    let kinds = if config.via.is_empty() { vec![NotifierKind::Terminal] } else { config.via.clone() };
+
+    let notifiers = kinds
+        .into_iter()
+        .map(|kind| -> Result<Box<dyn Notifier>, NudgelystError> {
+            match kind {
+                NotifierKind::Terminal => Ok(Box::new(TerminalNotifier::new(io::stdout(), date_format))),
+                #[cfg(feature = "desktop")]
+                NotifierKind::Desktop => Ok(Box::new(DesktopNotifier::session(date_format)?)),
+                #[cfg(not(feature = "desktop"))]
+                NotifierKind::Desktop => Err(NudgelystError::InvalidInput(NO_DESKTOP_SUPPORT.to_string())),
+                NotifierKind::Webhook => {
+                    let url = config.webhook_url.clone().ok_or_else(|| missing_setting("webhook", "webhook_url"))?;
+                    Ok(Box::new(WebhookNotifier::new(url, date_format)))
+                }
+                NotifierKind::Command => {
+                    let command = config.command.clone().ok_or_else(|| missing_setting("command", "command"))?;
+                    Ok(Box::new(CommandNotifier::new(command, date_format)))
+                }
+            }
+        })
+        .collect::<Result<Vec<Box<dyn Notifier>>, NudgelystError>>()?;
+
+    Ok(Box::new(AllNotifiers { notifiers }))
+}
+
+fn missing_setting(kind: &str, setting: &str) -> NudgelystError {
+    NudgelystError::InvalidInput(format!("nudging via {} needs `{}` set under [notify]", kind, setting))
+}
+
+/// Sends each nudge through several notifiers. A nudge counts as sent as long
+/// as one of them gets it through, so a working notifier isn't made to repeat
+/// itself while another is broken.
+struct AllNotifiers {
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Notifier for AllNotifiers {
+    fn notify(&self, reminder: &Reminder) -> Result<(), NudgelystError> {
+        let errors: Vec<String> = self
+            .notifiers
+            .iter()
+            .filter_map(|notifier| notifier.notify(reminder).err())
+            .map(|err| err.to_string())
+            .collect();
+
+        if errors.len() < self.notifiers.len() {
+            Ok(())
+        } else {
+            Err(NudgelystError::Notify(errors.join("; ")))
+        }
+    }
+}
+
+/// Prints nudges, ringing the terminal bell.
+#[derive(Debug)]
+pub struct TerminalNotifier<W: Write> {
+    out: Mutex<W>,
+    date_format: String,
+}
+
+impl<W: Write> TerminalNotifier<W> {
+    pub fn new(out: W, date_format: impl Into<String>) -> TerminalNotifier<W> {
+        TerminalNotifier { out: Mutex::new(out), date_format: date_format.into() }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<W: Write> Notifier for TerminalNotifier<W> {
+    fn notify(&self, reminder: &Reminder) -> Result<(), NudgelystError> {
+        let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+        writeln!(out, "\x07Nudge! {}", reminder.message(&self.date_format))
+            .and_then(|()| out.flush())
+            .map_err(|err| NudgelystError::Notify(err.to_string()))
+    }
+}
+
+/// Shows nudges as freedesktop desktop notifications.
+#[cfg(feature = "desktop")]
+#[derive(Debug)]
+pub struct DesktopNotifier {
+    connection: zbus::blocking::Connection,
+    date_format: String,
+}
+
+#[cfg(feature = "desktop")]
+impl DesktopNotifier {
+    /// Connects to the notification daemon on the session bus.
+    pub fn session(date_format: impl Into<String>) -> Result<DesktopNotifier, NudgelystError> {
+        let connection = zbus::blocking::Connection::session().map_err(|err| NudgelystError::Notify(err.to_string()))?;
+
+        Ok(DesktopNotifier::with_connection(connection, date_format))
+    }
+
+    pub fn with_connection(connection: zbus::blocking::Connection, date_format: impl Into<String>) -> DesktopNotifier {
+        DesktopNotifier { connection, date_format: date_format.into() }
+    }
+}
+
+#[cfg(feature = "desktop")]
+impl Notifier for DesktopNotifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), NudgelystError> {
+        let hints: std::collections::HashMap<&str, zbus::zvariant::Value> = std::collections::HashMap::new();
+        let actions: Vec<&str> = Vec::new();
+        // app name, id to replace, icon, summary, body, actions, hints, timeout (-1 = server default)
+        let body = ("nudgelyst", 0u32, "", "Nudge!", reminder.message(&self.date_format), actions, hints, -1i32);
+
+        self.connection
+            .call_method(
+                Some("org.freedesktop.Notifications"),
+                "/org/freedesktop/Notifications",
+                Some("org.freedesktop.Notifications"),
+                "Notify",
+                &body,
+            )
+            .map(|_| ())
+            .map_err(|err| NudgelystError::Notify(err.to_string()))
+    }
+}
+
+/// POSTs nudges as JSON to a URL.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    url: String,
+    date_format: String,
+}
+
+/// The JSON body of a webhook nudge.
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    id: usize,
+    name: &'a str,
+    due: Option<String>,
+    remind_at: String,
+    message: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>, date_format: impl Into<String>) -> WebhookNotifier {
+        WebhookNotifier { url: url.into(), date_format: date_format.into() }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), NudgelystError> {
+        let payload = WebhookPayload {
+            id: reminder.todo.id,
+            name: &reminder.todo.name,
+            due: reminder.todo.due.map(|due| due.to_rfc3339()),
+            remind_at: reminder.at.to_rfc3339(),
+            message: reminder.message(&self.date_format),
+        };
+        let body = serde_json::to_string(&payload).map_err(|err| NudgelystError::Notify(err.to_string()))?;
+
+        ureq::AgentBuilder::new()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+            .map(|_| ())
+            .map_err(|err| NudgelystError::Notify(err.to_string()))
+    }
+}
+
+/// Runs a shell command for each nudge, with the reminder in its environment:
+/// `NUDGELYST_ID`, `NUDGELYST_NAME`, `NUDGELYST_DUE` (RFC 3339, empty when
+/// there's no due date) and `NUDGELYST_MESSAGE`.
+#[derive(Debug, Clone)]
+pub struct CommandNotifier {
+    command: String,
+    date_format: String,
+}
+
+impl CommandNotifier {
+    pub fn new(command: impl Into<String>, date_format: impl Into<String>) -> CommandNotifier {
+        CommandNotifier { command: command.into(), date_format: date_format.into() }
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), NudgelystError> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("NUDGELYST_ID", reminder.todo.id.to_string())
+            .env("NUDGELYST_NAME", &reminder.todo.name)
+            .env("NUDGELYST_DUE", reminder.todo.due.map(|due| due.to_rfc3339()).unwrap_or_default())
+            .env("NUDGELYST_MESSAGE", reminder.message(&self.date_format))
+            .status()
+            .map_err(|err| NudgelystError::Notify(format!("couldn't run `{}`: {}", self.command, err)))?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(NudgelystError::Notify(format!("`{}` failed with {}", self.command, status)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::due::DUE_FORMAT;
+    use crate::todo::Todo;
+    use chrono::{Local, TimeZone};
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn reminder() -> Reminder {
+        let due = Local.with_ymd_and_hms(2024, 5, 1, 17, 0, 0).unwrap();
+
+        Reminder { todo: Todo { due: Some(due), ..Todo::new(3, "Pay rent".to_string()) }, at: due }
+    }
+
+    /// Answers a single HTTP request with `status`, handing back the request body.
+    fn serve_once(status: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/nudge", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(reader.get_mut(), "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+
+            String::from_utf8(body).unwrap()
+        });
+
+        (url, server)
+    }
+
+    #[test]
+    fn test_terminal_notifier() {
+        // Arrange
+        let notifier = TerminalNotifier::new(Vec::new(), DUE_FORMAT);
+
+        // Act
+        let result = notifier.notify(&reminder());
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(String::from_utf8(notifier.into_inner()).unwrap(), "\x07Nudge! 'Pay rent' is due now\n");
+    }
+
+    #[test]
+    fn test_webhook_notifier_posts_json() {
+        // Arrange
+        let (url, server) = serve_once("200 OK");
+        let notifier = WebhookNotifier::new(url, DUE_FORMAT);
+
+        // Act
+        let result = notifier.notify(&reminder());
+
+        // Assert
+        match result {
+            Ok(()) => {
+                let body: serde_json::Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+                assert_eq!(body["id"], 3);
+                assert_eq!(body["name"], "Pay rent");
+                assert_eq!(body["message"], "'Pay rent' is due now");
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_webhook_notifier_error_status() {
+        // Arrange
+        let (url, server) = serve_once("500 Internal Server Error");
+        let notifier = WebhookNotifier::new(url, DUE_FORMAT);
+
+        // Act
+        let result = notifier.notify(&reminder());
+
+        // Assert
+        server.join().unwrap();
+        assert!(matches!(result, Err(NudgelystError::Notify(_))));
+    }
+
+    #[test]
+    fn test_command_notifier_passes_reminder_in_env() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let notifier = CommandNotifier::new(format!("printf '%s|%s|%s' \"$NUDGELYST_ID\" \"$NUDGELYST_NAME\" \"$NUDGELYST_MESSAGE\" > '{}'", out.display()), DUE_FORMAT);
+
+        // Act
+        let result = notifier.notify(&reminder());
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(fs::read_to_string(out).unwrap(), "3|Pay rent|'Pay rent' is due now");
+    }
+
+    #[test]
+    fn test_command_notifier_failure() {
+        // Arrange
+        let notifier = CommandNotifier::new("exit 3", DUE_FORMAT);
+
+        // Act
+        let result = notifier.notify(&reminder());
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Notify(_))));
+    }
+
+    #[test]
+    fn test_from_config_needs_webhook_url() {
+        // Arrange
+        let config = NotifyConfig { via: vec![NotifierKind::Webhook], ..NotifyConfig::default() };
+
+        // Act
+        let result = from_config(&config, DUE_FORMAT);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_nudge_counts_as_sent_if_any_notifier_succeeds() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let config = NotifyConfig {
+            via: vec![NotifierKind::Command, NotifierKind::Webhook],
+            command: Some(format!("touch '{}'", out.display())),
+            // nothing listens on port 9 (discard) here
+            webhook_url: Some("http://127.0.0.1:9/nudge".to_string()),
+        };
+
+        // Act
+        let result = from_config(&config, DUE_FORMAT).unwrap().notify(&reminder());
+
+        // Assert
+        assert!(result.is_ok());
+        assert!(out.exists());
+    }
+
+    #[cfg(feature = "desktop")]
+    #[test]
+    fn test_desktop_notifier_calls_notify() {
+        use std::collections::HashMap;
+        use std::os::unix::net::UnixStream;
+        use std::sync::Arc;
+        use zbus::blocking::connection::Builder;
+        use zbus::zvariant::OwnedValue;
+
+        /// Stands in for the notification daemon, remembering what it was asked to show.
+        struct FakeNotifications {
+            shown: Arc<Mutex<Vec<(String, String)>>>,
+        }
+
+        #[zbus::interface(name = "org.freedesktop.Notifications")]
+        impl FakeNotifications {
+            #[allow(clippy::too_many_arguments)]
+            fn notify(
+                &self,
+                _app_name: String,
+                _replaces_id: u32,
+                _app_icon: String,
+                summary: String,
+                body: String,
+                _actions: Vec<String>,
+                _hints: HashMap<String, OwnedValue>,
+                _expire_timeout: i32,
+            ) -> u32 {
+                self.shown.lock().unwrap().push((summary, body));
+                1
+            }
+        }
+
+        // Arrange
+        let shown = Arc::new(Mutex::new(Vec::new()));
+        let fake = FakeNotifications { shown: Arc::clone(&shown) };
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = thread::spawn(move || {
+            Builder::async_io_unix_stream(server)
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at("/org/freedesktop/Notifications", fake)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = Builder::async_io_unix_stream(client).p2p().build().unwrap();
+        let _server = server.join().unwrap();
+        let notifier = DesktopNotifier::with_connection(client, DUE_FORMAT);
+
+        // Act
+        let result = notifier.notify(&reminder());
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(*shown.lock().unwrap(), vec![("Nudge!".to_string(), "'Pay rent' is due now".to_string())]);
+    }
+}
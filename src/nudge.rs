@@ -0,0 +1,432 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use crate::error::NudgelystError;
+use crate::file_todo_repository::sibling_path;
+use crate::todo::{Todo, TodoRepository};
+
+/// How often the nudger re-reads the list to pick up changes made while it sleeps.
+pub const POLL_INTERVAL: Duration = Duration::seconds(30);
+
+/// How late a reminder can still be sent, e.g. one that fell due while the
+/// nudger wasn't running. Anything older is dropped rather than sent.
+pub const MISSED_REMINDER_GRACE: Duration = Duration::hours(12);
+
+/// Where the nudger gets the time from, so tests can control it.
+pub trait Clock {
+    fn now(&self) -> DateTime<Local>;
+    /// Blocks until `when`, returning straight away if it has already passed.
+    fn sleep_until(&self, when: DateTime<Local>);
+}
+
+/// The real wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn sleep_until(&self, when: DateTime<Local>) {
+        if let Ok(wait) = (when - Local::now()).to_std() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> DateTime<Local> {
+        (**self).now()
+    }
+
+    fn sleep_until(&self, when: DateTime<Local>) {
+        (**self).sleep_until(when)
+    }
+}
+
+/// A single nudge about a todo, sent at `at`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reminder {
+    pub todo: Todo,
+    pub at: DateTime<Local>,
+}
+
+impl Reminder {
+    /// What the nudge says, e.g. `'Pay rent' is due Fri May 3 17:00`, with the due
+    /// date written in the strftime `date_format`.
+    pub fn message(&self, date_format: &str) -> String {
+        match self.todo.due {
+            Some(due) if due <= self.at => format!("'{}' is due now", self.todo.name),
+            Some(due) => format!("'{}' is due {}", self.todo.name, due.format(date_format)),
+            None => format!("'{}' needs doing", self.todo.name),
+        }
+    }
+}
+
+/// Every nudge an incomplete todo with a due date should get: one for each of
+/// its `remind_before` offsets and one when it falls due, earliest first.
+/// Offsets reaching back before the earliest representable time are skipped.
+pub fn reminders(todo: &Todo) -> Vec<Reminder> {
+    let due = match todo.due {
+        Some(due) if !todo.completed => due,
+        _ => return Vec::new(),
+    };
+
+    let mut times: Vec<DateTime<Local>> = todo
+        .remind_before
+        .iter()
+        .filter_map(|&minutes| {
+            let offset = Duration::try_minutes(i64::try_from(minutes).ok()?)?;
+            due.checked_sub_signed(offset)
+        })
+        .chain([due])
+        .collect();
+    times.sort();
+    times.dedup();
+
+    times
+        .into_iter()
+        .map(|at| Reminder { todo: todo.clone(), at })
+        .collect()
+}
+
+/// The file next to a todo list recording which of its reminders were sent, either
+/// for the whole store or, given a `list`, just for that list. Each nudger prunes
+/// its log down to the todos it sees, so they can't share one.
+pub fn delivered_path(todo_file: &Path, list: Option<&str>) -> PathBuf {
+    match list {
+        Some(list) => sibling_path(todo_file, &format!(".{}.nudged", list)),
+        None => sibling_path(todo_file, ".nudged"),
+    }
+}
+
+/// Which reminders have already been sent, so a restarted nudger doesn't send them again.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct DeliveredLog {
+    delivered: Vec<Delivered>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Delivered {
+    id: usize,
+    at: DateTime<Local>,
+}
+
+impl Delivered {
+    fn matches(&self, reminder: &Reminder) -> bool {
+        self.id == reminder.todo.id && self.at == reminder.at
+    }
+}
+
+/// Sends nudges for the todos in a repository as their reminders fall due.
+pub struct Nudger<'a, C: Clock> {
+    repo: &'a dyn TodoRepository,
+    clock: C,
+    delivered_path: PathBuf,
+}
+
+impl<'a, C: Clock> Nudger<'a, C> {
+    /// `delivered_path` is where sent reminders are recorded; see [`delivered_path`].
+    pub fn new(repo: &'a dyn TodoRepository, clock: C, delivered_path: impl Into<PathBuf>) -> Nudger<'a, C> {
+        Nudger { repo, clock, delivered_path: delivered_path.into() }
+    }
+
+    /// Nudges forever, waking for each reminder and at least every [`POLL_INTERVAL`].
+    /// A failed round is passed to `on_error` and retried on the next wake-up.
+    pub fn run<N, E>(&self, mut notify: N, mut on_error: E) -> !
+    where
+        N: FnMut(&Reminder) -> Result<(), NudgelystError>,
+        E: FnMut(NudgelystError),
+    {
+        loop {
+            if let Err(err) = self.nudge_due(&mut notify) {
+                on_error(err);
+            }
+
+            let poll_at = self.clock.now() + POLL_INTERVAL;
+            let wake_at = match self.next_reminder_at() {
+                Ok(Some(next)) => next.min(poll_at),
+                Ok(None) => poll_at,
+                Err(err) => {
+                    on_error(err);
+                    poll_at
+                }
+            };
+            self.clock.sleep_until(wake_at);
+        }
+    }
+
+    /// Sends every reminder that has fallen due and not been sent yet, returning
+    /// the ones sent. A reminder whose `notify` fails stays unsent.
+    pub fn nudge_due<N>(&self, mut notify: N) -> Result<Vec<Reminder>, NudgelystError>
+    where
+        N: FnMut(&Reminder) -> Result<(), NudgelystError>,
+    {
+        let now = self.clock.now();
+        let pending = self.pending_reminders()?;
+        let mut log = self.read_log()?;
+        let original_log = log.delivered.clone();
+
+        let mut sent = Vec::new();
+        let mut result = Ok(());
+        for reminder in pending.iter().filter(|reminder| reminder.at <= now && reminder.at > now - MISSED_REMINDER_GRACE) {
+            if log.delivered.iter().any(|delivered| delivered.matches(reminder)) {
+                continue;
+            }
+            if let Err(err) = notify(reminder) {
+                result = Err(err);
+                break;
+            }
+
+            log.delivered.push(Delivered { id: reminder.todo.id, at: reminder.at });
+            sent.push(reminder.clone());
+        }
+
+        // forget reminders of todos that were deleted, completed or rescheduled
+        log.delivered.retain(|delivered| pending.iter().any(|reminder| delivered.matches(reminder)));
+        if log.delivered != original_log {
+            self.write_log(&log)?;
+        }
+
+        result.map(|()| sent)
+    }
+
+    /// When the next reminder still to come is due.
+    pub fn next_reminder_at(&self) -> Result<Option<DateTime<Local>>, NudgelystError> {
+        let now = self.clock.now();
+
+        Ok(self
+            .pending_reminders()?
+            .into_iter()
+            .map(|reminder| reminder.at)
+            .filter(|&at| at > now)
+            .min())
+    }
+
+    fn pending_reminders(&self) -> Result<Vec<Reminder>, NudgelystError> {
+        Ok(self.repo.get_incomplete_todos(None)?.iter().flat_map(reminders).collect())
+    }
+
+    fn read_log(&self) -> Result<DeliveredLog, NudgelystError> {
+        if !self.delivered_path.exists() {
+            return Ok(DeliveredLog::default());
+        }
+
+        let content = fs::read_to_string(&self.delivered_path)?;
+        serde_yaml::from_str(&content).map_err(|err| NudgelystError::yaml(&self.delivered_path, err))
+    }
+
+    /// Written via a temp file and a rename, like the todo list itself.
+    fn write_log(&self, log: &DeliveredLog) -> Result<(), NudgelystError> {
+        let tmp_path = sibling_path(&self.delivered_path, ".tmp");
+        let content = serde_yaml::to_string(log).map_err(|err| NudgelystError::Storage(err.to_string()))?;
+
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(content.as_bytes())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.delivered_path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::due::DUE_FORMAT;
+    use crate::in_memory_todo_repository::InMemoryTodoRepository;
+    use chrono::TimeZone;
+    use std::cell::Cell;
+
+    /// A clock that only moves when told to, or when slept on.
+    struct FakeClock {
+        now: Cell<DateTime<Local>>,
+    }
+
+    impl FakeClock {
+        fn at(hour: u32, minute: u32) -> FakeClock {
+            FakeClock { now: Cell::new(time(hour, minute)) }
+        }
+
+        fn set(&self, hour: u32, minute: u32) {
+            self.now.set(time(hour, minute));
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> DateTime<Local> {
+            self.now.get()
+        }
+
+        fn sleep_until(&self, when: DateTime<Local>) {
+            self.now.set(self.now.get().max(when));
+        }
+    }
+
+    fn time(hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 1, hour, minute, 0).unwrap()
+    }
+
+    fn repo_with_due_todo(remind_before: Vec<u64>) -> InMemoryTodoRepository {
+        InMemoryTodoRepository::with_todos(vec![
+            Todo { due: Some(time(17, 0)), remind_before, ..Todo::new(0, "Pay rent".to_string()) },
+            Todo::new(0, "Someday".to_string()),
+        ]).unwrap()
+    }
+
+    fn sent_times(sent: Vec<Reminder>) -> Vec<DateTime<Local>> {
+        sent.into_iter().map(|reminder| reminder.at).collect()
+    }
+
+    #[test]
+    fn test_nudges_at_offsets_and_due_time() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_with_due_todo(vec![60]);
+        let clock = FakeClock::at(15, 0);
+        let nudger = Nudger::new(&repo, &clock, dir.path().join("nudgelyst.yaml.nudged"));
+
+        // Act
+        let before = nudger.nudge_due(|_| Ok(())).unwrap();
+        let next = nudger.next_reminder_at().unwrap();
+        clock.set(16, 0);
+        let at_offset = nudger.nudge_due(|_| Ok(())).unwrap();
+        clock.set(17, 0);
+        let at_due = nudger.nudge_due(|_| Ok(())).unwrap();
+
+        // Assert
+        assert!(before.is_empty());
+        assert_eq!(next, Some(time(16, 0)));
+        assert_eq!(sent_times(at_offset), vec![time(16, 0)]);
+        assert_eq!(sent_times(at_due), vec![time(17, 0)]);
+    }
+
+    #[test]
+    fn test_delivered_reminders_survive_restart() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let delivered = dir.path().join("nudgelyst.yaml.nudged");
+        let repo = repo_with_due_todo(vec![]);
+        let clock = FakeClock::at(17, 5);
+        Nudger::new(&repo, &clock, &delivered).nudge_due(|_| Ok(())).unwrap();
+
+        // Act
+        let result = Nudger::new(&repo, &clock, &delivered).nudge_due(|_| Ok(()));
+
+        // Assert
+        match result {
+            Ok(sent) => {
+                assert!(sent.is_empty());
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_missed_reminders_within_grace_are_sent_once() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_with_due_todo(vec![60 * 24, 60]);
+        let clock = FakeClock::at(17, 30);
+        let nudger = Nudger::new(&repo, &clock, dir.path().join("nudgelyst.yaml.nudged"));
+
+        // Act
+        let sent = nudger.nudge_due(|_| Ok(())).unwrap();
+
+        // Assert
+        // the reminder a day ahead is too stale to be worth sending
+        assert_eq!(sent_times(sent), vec![time(16, 0), time(17, 0)]);
+    }
+
+    #[test]
+    fn test_completed_todos_are_not_nudged() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_with_due_todo(vec![]);
+        repo.update_todo(Todo { completed: true, ..repo.get_todo(1).unwrap() }).unwrap();
+        let clock = FakeClock::at(17, 0);
+        let nudger = Nudger::new(&repo, &clock, dir.path().join("nudgelyst.yaml.nudged"));
+
+        // Act
+        let sent = nudger.nudge_due(|_| Ok(())).unwrap();
+
+        // Assert
+        assert!(sent.is_empty());
+        assert_eq!(nudger.next_reminder_at().unwrap(), None);
+    }
+
+    #[test]
+    fn test_failed_nudge_is_retried() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_with_due_todo(vec![]);
+        let clock = FakeClock::at(17, 0);
+        let nudger = Nudger::new(&repo, &clock, dir.path().join("nudgelyst.yaml.nudged"));
+
+        // Act
+        let failed = nudger.nudge_due(|_| Err(NudgelystError::Storage("no terminal".to_string())));
+        let retried = nudger.nudge_due(|_| Ok(()));
+
+        // Assert
+        assert!(matches!(failed, Err(NudgelystError::Storage(_))));
+        assert_eq!(sent_times(retried.unwrap()), vec![time(17, 0)]);
+    }
+
+    #[test]
+    fn test_rescheduled_todo_is_nudged_again() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_with_due_todo(vec![]);
+        let clock = FakeClock::at(17, 0);
+        let nudger = Nudger::new(&repo, &clock, dir.path().join("nudgelyst.yaml.nudged"));
+        nudger.nudge_due(|_| Ok(())).unwrap();
+        repo.update_todo(Todo { due: Some(time(18, 0)), ..repo.get_todo(1).unwrap() }).unwrap();
+
+        // Act
+        clock.sleep_until(nudger.next_reminder_at().unwrap().unwrap());
+        let sent = nudger.nudge_due(|_| Ok(())).unwrap();
+
+        // Assert
+        assert_eq!(sent_times(sent), vec![time(18, 0)]);
+    }
+
+    #[test]
+    fn test_delivered_path() {
+        let todo_file = Path::new("/home/me/nudgelyst.yaml");
+
+        assert_eq!(delivered_path(todo_file, None), Path::new("/home/me/nudgelyst.yaml.nudged"));
+        assert_eq!(delivered_path(todo_file, Some("work")), Path::new("/home/me/nudgelyst.yaml.work.nudged"));
+    }
+
+    #[test]
+    fn test_reminder_message() {
+        // Arrange
+        let todo = Todo { due: Some(time(17, 0)), remind_before: vec![30], ..Todo::new(1, "Pay rent".to_string()) };
+
+        // Act
+        let messages: Vec<String> = reminders(&todo).iter().map(|reminder| reminder.message(DUE_FORMAT)).collect();
+        let custom = reminders(&todo)[0].message("%d/%m %H:%M");
+
+        // Assert
+        assert_eq!(messages, vec!["'Pay rent' is due Wed May 1 17:00", "'Pay rent' is due now"]);
+        assert_eq!(custom, "'Pay rent' is due 01/05 17:00");
+    }
+
+    #[test]
+    fn test_reminders_out_of_range_are_skipped() {
+        // Arrange
+        let todo = Todo { due: Some(time(17, 0)), remind_before: vec![720_000_000_000, u64::MAX, 60], ..Todo::new(1, "Pay rent".to_string()) };
+
+        // Act
+        let reminders = reminders(&todo);
+
+        // Assert
+        assert_eq!(sent_times(reminders), vec![time(16, 0), time(17, 0)]);
+    }
+}
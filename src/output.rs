@@ -0,0 +1,268 @@
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::error::NudgelystError;
+use crate::recurrence::Recurrence;
+use crate::todo::{Priority, Todo};
+
+/// Version of the records `json` and `yaml` output is made of, printed alongside
+/// them; `csv` and `tsv` don't carry it, so go by their header row. It's bumped
+/// when a field is renamed or removed or changes meaning; new fields can be added
+/// without bumping it, so consumers should ignore fields they don't know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How listed todos are printed.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Colored, for reading in a terminal
+    #[default]
+    Table,
+    /// One uncolored line per todo
+    Plain,
+    Json,
+    Yaml,
+    /// A header row followed by a row per todo
+    Csv,
+    /// Like csv, separated by tabs
+    Tsv,
+}
+
+#[derive(Serialize)]
+struct Output<'a> {
+    schema_version: u32,
+    todos: Vec<TodoRecord<'a>>,
+}
+
+/// A todo as other programs see it. Unlike the stored form every field is always
+/// present, empty or not.
+#[derive(Serialize)]
+struct TodoRecord<'a> {
+    id: usize,
+    name: &'a str,
+    completed: bool,
+    priority: Priority,
+    due: Option<DateTime<Local>>,
+    /// Minutes before `due`.
+    remind_before: &'a [u64],
+    recurrence: Option<&'a Recurrence>,
+    tags: &'a [String],
+    notes: &'a str,
+    parent_id: Option<usize>,
+    depends_on: &'a [usize],
+    created_at: Option<DateTime<Local>>,
+    updated_at: Option<DateTime<Local>>,
+    completed_at: Option<DateTime<Local>>,
+    revision: u64,
+    list: &'a str,
+}
+
+impl<'a> From<&'a Todo> for TodoRecord<'a> {
+    fn from(todo: &'a Todo) -> TodoRecord<'a> {
+        TodoRecord {
+            id: todo.id,
+            name: &todo.name,
+            completed: todo.completed,
+            priority: todo.priority,
+            due: todo.due,
+            remind_before: &todo.remind_before,
+            recurrence: todo.recurrence.as_ref(),
+            tags: &todo.tags,
+            notes: &todo.notes,
+            parent_id: todo.parent_id,
+            depends_on: &todo.depends_on,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            completed_at: todo.completed_at,
+            revision: todo.revision,
+            list: todo.list_name(),
+        }
+    }
+}
+
+impl TodoRecord<'_> {
+    /// The record as named text fields, in the order CSV and TSV columns come in.
+    /// Lists are joined with commas, except tags which are joined with spaces.
+    fn fields(&self) -> [(&'static str, String); 16] {
+        // written the same way serde writes them for json and yaml
+        let moment = |moment: Option<DateTime<Local>>| {
+            moment.map(|moment| moment.to_rfc3339_opts(SecondsFormat::AutoSi, true)).unwrap_or_default()
+        };
+        let join = |numbers: Vec<String>| numbers.join(",");
+
+        [
+            ("id", self.id.to_string()),
+            ("name", self.name.to_string()),
+            ("completed", self.completed.to_string()),
+            ("priority", self.priority.to_string()),
+            ("due", moment(self.due)),
+            ("remind_before", join(self.remind_before.iter().map(u64::to_string).collect())),
+            ("recurrence", self.recurrence.map(Recurrence::to_string).unwrap_or_default()),
+            ("tags", self.tags.join(" ")),
+            ("notes", self.notes.to_string()),
+            ("parent_id", self.parent_id.map(|id| id.to_string()).unwrap_or_default()),
+            ("depends_on", join(self.depends_on.iter().map(usize::to_string).collect())),
+            ("created_at", moment(self.created_at)),
+            ("updated_at", moment(self.updated_at)),
+            ("completed_at", moment(self.completed_at)),
+            ("revision", self.revision.to_string()),
+            ("list", self.list.to_string()),
+        ]
+    }
+}
+
+/// Renders `todos` in `format`. `Table` comes out the same as `Plain`; tables are
+/// drawn by `table::render_table`, which needs the whole list to work from. Plain
+/// lines write due dates in the strftime `date_format`.
+pub fn render(todos: &[Todo], format: OutputFormat, date_format: &str) -> Result<String, NudgelystError> {
+    let records: Vec<TodoRecord> = todos.iter().map(TodoRecord::from).collect();
+    let output = Output { schema_version: SCHEMA_VERSION, todos: records };
+
+    match format {
+        OutputFormat::Table | OutputFormat::Plain => Ok(todos.iter().map(|todo| plain_line(todo, date_format) + "\n").collect()),
+        OutputFormat::Json => serde_json::to_string_pretty(&output)
+            .map(|json| json + "\n")
+            .map_err(|err| NudgelystError::Storage(err.to_string())),
+        OutputFormat::Yaml => serde_yaml::to_string(&output).map_err(|err| NudgelystError::Storage(err.to_string())),
+        OutputFormat::Csv => Ok(delimited(&output.todos, ",", csv_field)),
+        OutputFormat::Tsv => Ok(delimited(&output.todos, "\t", tsv_field)),
+    }
+}
+
+/// `3 [ ] Buy milk +home [notes] (due Mon May 6 09:00, high priority)`
+fn plain_line(todo: &Todo, date_format: &str) -> String {
+    let check = if todo.completed { "x" } else { " " };
+    let tags: String = todo.tags.iter().map(|tag| format!(" +{}", tag)).collect();
+    let notes = if todo.notes.is_empty() { "" } else { " [notes]" };
+
+    let mut details = Vec::new();
+    if let Some(due) = todo.due {
+        details.push(format!("due {}", due.format(date_format)));
+    }
+    if todo.priority != Priority::Medium {
+        details.push(format!("{} priority", todo.priority));
+    }
+    let details = if details.is_empty() { String::new() } else { format!(" ({})", details.join(", ")) };
+
+    format!("{} [{}] {}{}{}{}", todo.id, check, todo.name, tags, notes, details)
+}
+
+fn delimited(records: &[TodoRecord], separator: &str, escape: fn(&str) -> String) -> String {
+    let header: Vec<&str> = TodoRecord::from(&Todo::new(0, String::new())).fields().iter().map(|(name, _)| *name).collect();
+
+    let mut output = header.join(separator) + "\n";
+    for record in records {
+        let row: Vec<String> = record.fields().iter().map(|(_, value)| escape(value)).collect();
+        output.push_str(&row.join(separator));
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Quotes a field as RFC 4180 asks, when it holds anything that would break the row.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// TSV can't quote, so tabs, line breaks and backslashes are escaped with backslashes.
+fn tsv_field(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::due::DUE_FORMAT;
+    use chrono::TimeZone;
+
+    fn todos() -> Vec<Todo> {
+        vec![
+            Todo {
+                due: Some(Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap()),
+                priority: Priority::High,
+                tags: vec!["home".to_string()],
+                notes: "oat, \"barista\" kind\nfrom the corner shop".to_string(),
+                ..Todo::new(3, "Buy milk".to_string())
+            },
+            Todo { completed: true, ..Todo::new(4, "Call mum".to_string()) },
+        ]
+    }
+
+    #[test]
+    fn test_render_json_has_every_field() {
+        // Act
+        let result = render(&todos(), OutputFormat::Json, DUE_FORMAT);
+
+        // Assert
+        match result {
+            Ok(json) => {
+                let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+                assert_eq!(value["schema_version"], SCHEMA_VERSION);
+                assert_eq!(value["todos"][0]["priority"], "high");
+                assert_eq!(value["todos"][0]["tags"], serde_json::json!(["home"]));
+                assert_eq!(value["todos"][1]["due"], serde_json::Value::Null);
+                assert_eq!(value["todos"][1]["depends_on"], serde_json::json!([]));
+                assert_eq!(value["todos"][1]["list"], "default");
+                assert_eq!(value["todos"][1].as_object().unwrap().len(), 16);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_csv_quotes_fields() {
+        // Act
+        let csv = render(&todos(), OutputFormat::Csv, DUE_FORMAT).unwrap();
+
+        // Assert
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "id,name,completed,priority,due,remind_before,recurrence,tags,notes,parent_id,depends_on,created_at,updated_at,completed_at,revision,list"
+        );
+        assert!(csv.contains(",home,\"oat, \"\"barista\"\" kind\nfrom the corner shop\",,"));
+        assert!(csv.ends_with("4,Call mum,true,medium,,,,,,,,,,,0,default\n"));
+    }
+
+    #[test]
+    fn test_render_tsv_escapes_fields() {
+        // Act
+        let tsv = render(&todos(), OutputFormat::Tsv, DUE_FORMAT).unwrap();
+
+        // Assert
+        assert_eq!(tsv.lines().count(), 3);
+        assert!(tsv.contains("\toat, \"barista\" kind\\nfrom the corner shop\t"));
+    }
+
+    #[test]
+    fn test_render_plain() {
+        // Act
+        let plain = render(&todos(), OutputFormat::Plain, DUE_FORMAT).unwrap();
+        let custom = render(&todos(), OutputFormat::Plain, "%Y-%m-%d").unwrap();
+
+        // Assert
+        assert_eq!(plain, "3 [ ] Buy milk +home [notes] (due Mon May 6 09:00, high priority)\n4 [x] Call mum\n");
+        assert!(custom.starts_with("3 [ ] Buy milk +home [notes] (due 2024-05-06, high priority)\n"));
+    }
+
+    #[test]
+    fn test_render_csv_header_without_todos() {
+        // Act
+        let csv = render(&[], OutputFormat::Csv, DUE_FORMAT).unwrap();
+
+        // Assert
+        assert!(csv.starts_with("id,name,"));
+        assert_eq!(csv.lines().count(), 1);
+    }
+}
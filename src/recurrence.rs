@@ -0,0 +1,456 @@
+use chrono::{DateTime, Datelike, Days, Duration, Local, Months, NaiveDate, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use crate::error::NudgelystError;
+
+const WEEKDAYS: [Weekday; 7] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// How often a todo comes back, stored as a subset of an iCalendar RRULE:
+/// `FREQ` (`DAILY`, `WEEKLY` or `MONTHLY`), `INTERVAL`, `BYDAY` (daily and weekly
+/// only) and `BYMONTHDAY` (monthly only, clamped to the end of shorter months).
+///
+/// Parsing also takes friendlier forms: `daily`, `weekly`, `monthly`,
+/// `every 3 days`, `every 2 weeks`, `weekdays`, `weekends` and lists of days
+/// like `mon,thu` or `every fri`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// Repeat every `interval` days, weeks or months.
+    pub interval: u32,
+    /// Only these days of the week; any day when empty.
+    pub by_day: Vec<Weekday>,
+    /// The day of the month a monthly rule falls on. Unset until the first
+    /// occurrence is known, which then sets it.
+    pub by_month_day: Option<u32>,
+}
+
+impl Recurrence {
+    pub fn new(frequency: Frequency, interval: u32) -> Recurrence {
+        Recurrence { frequency, interval, by_day: Vec::new(), by_month_day: None }
+    }
+
+    /// The rule pinned to the day of the month `due` falls on, if it's monthly and
+    /// not pinned yet, so a month clamped short doesn't move later months too.
+    pub fn anchored(&self, due: NaiveDate) -> Recurrence {
+        match (self.frequency, self.by_month_day) {
+            (Frequency::Monthly, None) => Recurrence { by_month_day: Some(due.day()), ..self.clone() },
+            _ => self.clone(),
+        }
+    }
+
+    /// The first day on or after `date` that the rule allows.
+    pub fn first_on_or_after(&self, date: NaiveDate) -> NaiveDate {
+        (0..7)
+            .filter_map(|days| date.checked_add_days(Days::new(days)))
+            .find(|date| self.allows(*date))
+            .unwrap_or(date)
+    }
+
+    /// When the next occurrence after one due at `due` falls due. It keeps
+    /// the time of day, and skips any occurrences that would already be
+    /// overdue at `now` so completing a chore late doesn't leave a backlog.
+    /// Fails when the next occurrence is too far ahead to represent.
+    pub fn next_due(&self, due: DateTime<Local>, now: DateTime<Local>) -> Result<DateTime<Local>, NudgelystError> {
+        let out_of_range = || NudgelystError::InvalidInput(format!(
+            "the next occurrence of '{}' is too far ahead; drop it with `edit --no-repeat`",
+            self.describe()
+        ));
+        let rule = self.anchored(due.date_naive());
+        let mut date = due.date_naive();
+
+        loop {
+            date = rule.next_date(date).ok_or_else(out_of_range)?;
+            let next = match Local.from_local_datetime(&date.and_time(due.time())).earliest() {
+                Some(next) => next,
+                // the time doesn't exist on that day because of a DST change
+                None => due
+                    .checked_add_signed(Duration::days((date - due.date_naive()).num_days()))
+                    .ok_or_else(out_of_range)?,
+            };
+
+            if next > now {
+                return Ok(next);
+            }
+        }
+    }
+
+    /// The occurrence strictly after `date`, or `None` past the last representable date.
+    fn next_date(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let interval = u64::from(self.interval.max(1));
+
+        match self.frequency {
+            Frequency::Daily => {
+                for step in 1..=7 {
+                    let next = date.checked_add_days(Days::new(interval * step))?;
+                    if self.allows(next) {
+                        return Some(next);
+                    }
+                }
+                // stepping never lands on an allowed day, e.g. every 7 days on other weekdays
+                Some(self.first_on_or_after(date.succ_opt()?))
+            }
+            Frequency::Weekly if self.by_day.is_empty() => date.checked_add_days(Days::new(interval * 7)),
+            Frequency::Weekly => {
+                // later in the same week, otherwise the first allowed day `interval` weeks on
+                let days_to_sunday = 6 - u64::from(date.weekday().num_days_from_monday());
+                let later_this_week = (1..=days_to_sunday)
+                    .filter_map(|days| date.checked_add_days(Days::new(days)))
+                    .find(|date| self.allows(*date));
+
+                match later_this_week {
+                    Some(next) => Some(next),
+                    None => {
+                        let monday = date.checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))?;
+                        Some(self.first_on_or_after(monday.checked_add_days(Days::new(interval * 7))?))
+                    }
+                }
+            }
+            // clamped to the end of shorter months
+            Frequency::Monthly => {
+                let month = date.with_day(1)?.checked_add_months(Months::new(self.interval.max(1)))?;
+                let days_in_month = month.checked_add_months(Months::new(1))?.pred_opt()?.day();
+
+                month.with_day(self.by_month_day.unwrap_or(date.day()).min(days_in_month))
+            }
+        }
+    }
+
+    /// Days in week order without repeats, so equal rules compare equal.
+    fn normalized(mut self) -> Recurrence {
+        self.by_day.sort_by_key(|day| day.num_days_from_monday());
+        self.by_day.dedup();
+        self
+    }
+
+    fn allows(&self, date: NaiveDate) -> bool {
+        self.by_day.is_empty() || self.by_day.contains(&date.weekday())
+    }
+
+    /// Reads like "every 2 weeks on Mon, Thu".
+    pub fn describe(&self) -> String {
+        let unit = match self.frequency {
+            Frequency::Daily => "day",
+            Frequency::Weekly => "week",
+            Frequency::Monthly => "month",
+        };
+        let every = if self.interval > 1 { format!("every {} {}s", self.interval, unit) } else { format!("every {}", unit) };
+
+        if let Some(day) = self.by_month_day {
+            format!("{} on day {}", every, day)
+        } else if self.by_day.is_empty() {
+            every
+        } else {
+            let days: Vec<String> = self.by_day.iter().map(|day| day.to_string()).collect();
+            format!("{} on {}", every, days.join(", "))
+        }
+    }
+}
+
+impl fmt::Display for Recurrence {
+    /// Writes the rule as an RRULE, e.g. `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+        };
+        write!(f, "FREQ={}", frequency)?;
+
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self.by_day.iter().map(|day| rrule_day(*day)).collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(day) = self.by_month_day {
+            write!(f, ";BYMONTHDAY={}", day)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = NudgelystError;
+
+    fn from_str(input: &str) -> Result<Recurrence, NudgelystError> {
+        let rule = input.trim().to_lowercase();
+        let rule = rule.strip_prefix("rrule:").unwrap_or(&rule);
+
+        let recurrence = if rule.starts_with("freq=") {
+            parse_rrule(rule)?
+        } else {
+            parse_friendly(rule).ok_or_else(|| {
+                NudgelystError::InvalidInput(format!(
+                    "couldn't understand the recurrence '{}'; try something like 'daily', 'every 2 weeks', 'mon,thu' or 'FREQ=WEEKLY;BYDAY=MO'",
+                    input
+                ))
+            })?
+        };
+
+        Ok(recurrence)
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = NudgelystError;
+
+    fn try_from(rule: String) -> Result<Recurrence, NudgelystError> {
+        rule.parse()
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> String {
+        recurrence.to_string()
+    }
+}
+
+fn parse_rrule(rule: &str) -> Result<Recurrence, NudgelystError> {
+    let invalid = |message: String| NudgelystError::InvalidInput(format!("invalid recurrence rule '{}': {}", rule, message));
+    let mut recurrence = Recurrence::new(Frequency::Daily, 1);
+
+    for part in rule.split(';').filter(|part| !part.is_empty()) {
+        let (key, value) = part.split_once('=').ok_or_else(|| invalid(format!("'{}' isn't KEY=VALUE", part)))?;
+
+        match key {
+            "freq" => {
+                recurrence.frequency = match value {
+                    "daily" => Frequency::Daily,
+                    "weekly" => Frequency::Weekly,
+                    "monthly" => Frequency::Monthly,
+                    _ => return Err(invalid(format!("FREQ={} isn't supported", value.to_uppercase()))),
+                }
+            }
+            "interval" => {
+                recurrence.interval = value
+                    .parse()
+                    .ok()
+                    .filter(|&interval| interval > 0)
+                    .ok_or_else(|| invalid(format!("INTERVAL={} isn't a positive number", value)))?
+            }
+            "byday" => {
+                recurrence.by_day = value
+                    .split(',')
+                    .map(|day| parse_weekday(day).ok_or_else(|| invalid(format!("'{}' isn't a day", day.to_uppercase()))))
+                    .collect::<Result<Vec<Weekday>, NudgelystError>>()?
+            }
+            "bymonthday" => {
+                recurrence.by_month_day = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|day| (1..=31).contains(day))
+                        .ok_or_else(|| invalid(format!("BYMONTHDAY={} isn't a day from 1 to 31", value)))?,
+                )
+            }
+            _ => return Err(invalid(format!("{} isn't supported", key.to_uppercase()))),
+        }
+    }
+
+    match recurrence.frequency {
+        Frequency::Monthly if !recurrence.by_day.is_empty() => {
+            return Err(invalid("BYDAY isn't supported with FREQ=MONTHLY".to_string()))
+        }
+        Frequency::Daily | Frequency::Weekly if recurrence.by_month_day.is_some() => {
+            return Err(invalid("BYMONTHDAY is only supported with FREQ=MONTHLY".to_string()))
+        }
+        _ => {}
+    }
+
+    Ok(recurrence.normalized())
+}
+
+fn parse_friendly(rule: &str) -> Option<Recurrence> {
+    let rule = rule.strip_prefix("every ").unwrap_or(rule).trim();
+
+    let recurrence = match rule {
+        "daily" | "day" => Recurrence::new(Frequency::Daily, 1),
+        "weekly" | "week" => Recurrence::new(Frequency::Weekly, 1),
+        "monthly" | "month" => Recurrence::new(Frequency::Monthly, 1),
+        "weekday" | "weekdays" => Recurrence { by_day: WEEKDAYS[..5].to_vec(), ..Recurrence::new(Frequency::Weekly, 1) },
+        "weekend" | "weekends" => Recurrence { by_day: WEEKDAYS[5..].to_vec(), ..Recurrence::new(Frequency::Weekly, 1) },
+        _ => match rule.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [interval, unit] if interval.parse::<u32>().is_ok_and(|interval| interval > 0) => {
+                let frequency = match unit.trim_end_matches('s') {
+                    "day" => Frequency::Daily,
+                    "week" => Frequency::Weekly,
+                    "month" => Frequency::Monthly,
+                    _ => return None,
+                };
+                Recurrence::new(frequency, interval.parse().ok()?)
+            }
+            _ => {
+                let by_day = rule
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|day| !day.is_empty() && *day != "and")
+                    .map(|day| day.parse::<Weekday>().ok())
+                    .collect::<Option<Vec<Weekday>>>()
+                    .filter(|by_day| !by_day.is_empty())?;
+                Recurrence { by_day, ..Recurrence::new(Frequency::Weekly, 1) }
+            }
+        },
+    };
+
+    Some(recurrence.normalized())
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    WEEKDAYS.into_iter().find(|weekday| rrule_day(*weekday).eq_ignore_ascii_case(day))
+}
+
+fn rrule_day(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap()
+    }
+
+    fn parse(rule: &str) -> Recurrence {
+        match rule.parse() {
+            Ok(recurrence) => recurrence,
+            Err(err) => panic!("Unexpected error for '{}': {:?}", rule, err),
+        }
+    }
+
+    #[test]
+    fn test_parse_friendly_forms_as_rrules() {
+        assert_eq!(parse("daily").to_string(), "FREQ=DAILY");
+        assert_eq!(parse("every 3 days").to_string(), "FREQ=DAILY;INTERVAL=3");
+        assert_eq!(parse("every 2 weeks").to_string(), "FREQ=WEEKLY;INTERVAL=2");
+        assert_eq!(parse("Monthly").to_string(), "FREQ=MONTHLY");
+        assert_eq!(parse("weekdays").to_string(), "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR");
+        assert_eq!(parse("every thu and mon").to_string(), "FREQ=WEEKLY;BYDAY=MO,TH");
+    }
+
+    #[test]
+    fn test_parse_rrule_round_trips() {
+        // Arrange
+        let rule = "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR";
+
+        // Act
+        let recurrence = parse(&format!("RRULE:{}", rule));
+
+        // Assert
+        assert_eq!(recurrence.by_day, vec![Weekday::Mon, Weekday::Fri]);
+        assert_eq!(recurrence.to_string(), rule);
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        for rule in [
+            "FREQ=YEARLY",
+            "FREQ=DAILY;COUNT=3",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=MONTHLY;BYDAY=MO",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=WEEKLY;BYMONTHDAY=3",
+            "every 0 days",
+            "fortnightly",
+            "",
+        ] {
+            // Act
+            let result = rule.parse::<Recurrence>();
+
+            // Assert
+            assert!(matches!(result, Err(NudgelystError::InvalidInput(_))), "parsing '{}' gave {:?}", rule, result);
+        }
+    }
+
+    #[test]
+    fn test_next_due_keeps_time_of_day() {
+        // 2024-05-01 is a Wednesday
+        assert_eq!(parse("daily").next_due(at(1, 9), at(1, 10)).unwrap(), at(2, 9));
+        assert_eq!(parse("every 3 days").next_due(at(1, 9), at(1, 10)).unwrap(), at(4, 9));
+        assert_eq!(parse("weekly").next_due(at(1, 9), at(1, 10)).unwrap(), at(8, 9));
+        assert_eq!(parse("monthly").next_due(at(1, 9), at(1, 10)).unwrap(), Local.with_ymd_and_hms(2024, 6, 1, 9, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_next_due_on_weekdays() {
+        assert_eq!(parse("mon,thu").next_due(at(1, 9), at(1, 10)).unwrap(), at(2, 9));
+        assert_eq!(parse("mon,thu").next_due(at(2, 9), at(2, 10)).unwrap(), at(6, 9));
+        assert_eq!(parse("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO").next_due(at(6, 9), at(6, 10)).unwrap(), at(20, 9));
+        assert_eq!(parse("weekdays").next_due(at(3, 9), at(3, 10)).unwrap(), at(6, 9));
+    }
+
+    #[test]
+    fn test_next_due_skips_missed_occurrences() {
+        // Act
+        let next = parse("daily").next_due(at(1, 9), at(5, 12)).unwrap();
+
+        // Assert
+        assert_eq!(next, at(6, 9));
+    }
+
+    #[test]
+    fn test_next_due_monthly_keeps_the_day_of_month() {
+        // Arrange
+        let on = |month: u32, day: u32| Local.with_ymd_and_hms(2027, month, day, 9, 0, 0).unwrap();
+        let rule = parse("monthly");
+        let anchored = rule.anchored(on(1, 31).date_naive());
+
+        // Act
+        let february = rule.next_due(on(1, 31), on(1, 31)).unwrap();
+        let march = anchored.next_due(february, february).unwrap();
+
+        // Assert
+        assert_eq!(february, on(2, 28));
+        assert_eq!(march, on(3, 31));
+        assert_eq!(anchored.to_string(), "FREQ=MONTHLY;BYMONTHDAY=31");
+        assert_eq!(parse("FREQ=MONTHLY;BYMONTHDAY=30").next_due(on(2, 28), on(2, 28)).unwrap(), on(3, 30));
+    }
+
+    #[test]
+    fn test_next_due_too_far_ahead() {
+        for rule in ["every 4000000000 days", "every 4000000000 weeks", "FREQ=MONTHLY;INTERVAL=4000000000", "FREQ=WEEKLY;INTERVAL=4000000000;BYDAY=MO"] {
+            // Act
+            let result = parse(rule).next_due(at(6, 9), at(6, 10));
+
+            // Assert
+            assert!(matches!(result, Err(NudgelystError::InvalidInput(_))), "{} gave {:?}", rule, result);
+        }
+    }
+
+    #[test]
+    fn test_first_on_or_after() {
+        // Arrange
+        let wednesday = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+
+        // Act
+        let first = parse("mon").first_on_or_after(wednesday);
+
+        // Assert
+        assert_eq!(first, NaiveDate::from_ymd_opt(2024, 5, 6).unwrap());
+        assert_eq!(parse("daily").first_on_or_after(wednesday), wednesday);
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(parse("daily").describe(), "every day");
+        assert_eq!(parse("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH").describe(), "every 2 weeks on Mon, Thu");
+    }
+}
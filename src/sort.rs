@@ -0,0 +1,82 @@
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use crate::todo::Todo;
+
+/// The orders `list` can show todos in.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Overdue first, then by priority, then soonest due
+    #[default]
+    Smart,
+    /// Highest priority first
+    Priority,
+    /// Soonest due first; todos without a due date last
+    Due,
+    /// Oldest first, the order todos were added in
+    Id,
+    /// Alphabetically by name
+    Name,
+}
+
+/// Sorts `todos` in place. Ties always fall back to id order.
+pub fn sort_todos(todos: &mut [Todo], order: SortOrder, now: DateTime<Local>) {
+    todos.sort_by(|a, b| compare(a, b, order, now).then(a.id.cmp(&b.id)));
+}
+
+fn compare(a: &Todo, b: &Todo, order: SortOrder, now: DateTime<Local>) -> Ordering {
+    match order {
+        SortOrder::Smart => Reverse(a.is_overdue(now))
+            .cmp(&Reverse(b.is_overdue(now)))
+            .then(compare(a, b, SortOrder::Priority, now))
+            .then(compare(a, b, SortOrder::Due, now)),
+        SortOrder::Priority => b.priority.cmp(&a.priority),
+        // None sorts before Some, so compare whether it's missing first
+        SortOrder::Due => (a.due.is_none(), a.due).cmp(&(b.due.is_none(), b.due)),
+        SortOrder::Id => Ordering::Equal,
+        SortOrder::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::Priority;
+    use chrono::TimeZone;
+
+    fn at(day: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap()
+    }
+
+    fn todos() -> Vec<Todo> {
+        vec![
+            Todo { priority: Priority::Low, ..Todo::new(1, "walk dog".to_string()) },
+            Todo { due: Some(at(9)), ..Todo::new(2, "Buy milk".to_string()) },
+            Todo { priority: Priority::High, due: Some(at(20)), ..Todo::new(3, "ship release".to_string()) },
+            Todo { priority: Priority::Low, due: Some(at(2)), ..Todo::new(4, "file taxes".to_string()) },
+            Todo { priority: Priority::High, ..Todo::new(5, "call mum".to_string()) },
+        ]
+    }
+
+    fn sorted_ids(order: SortOrder) -> Vec<usize> {
+        let mut todos = todos();
+        sort_todos(&mut todos, order, at(5));
+
+        todos.iter().map(|todo| todo.id).collect()
+    }
+
+    #[test]
+    fn test_smart_puts_overdue_then_priority_first() {
+        assert_eq!(sorted_ids(SortOrder::Smart), vec![4, 3, 5, 2, 1]);
+    }
+
+    #[test]
+    fn test_other_orders() {
+        assert_eq!(sorted_ids(SortOrder::Priority), vec![3, 5, 2, 1, 4]);
+        assert_eq!(sorted_ids(SortOrder::Due), vec![4, 2, 3, 1, 5]);
+        assert_eq!(sorted_ids(SortOrder::Id), vec![1, 2, 3, 4, 5]);
+        assert_eq!(sorted_ids(SortOrder::Name), vec![2, 5, 4, 3, 1]);
+    }
+}
@@ -0,0 +1,880 @@
+use chrono::{DateTime, Local, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::error::NudgelystError;
+use crate::file_todo_repository::FileTodoRepository;
+use crate::lists::{count_lists, list_field, validate_list_name, TodoList, DEFAULT_LIST};
+use crate::recurrence::Recurrence;
+use crate::todo::{normalize_tag, Priority, Todo, TodoRepository};
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how many
+/// have already run against a database.
+const MIGRATIONS: &[&str] = &[
+    // AUTOINCREMENT keeps ids from being reused after a delete, matching the file backend
+    "CREATE TABLE todos (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        completed INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX todos_name ON todos (name);
+    CREATE INDEX todos_completed ON todos (completed);",
+    "ALTER TABLE todos ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;",
+    // kept in UTC so the text sorts in time order
+    "ALTER TABLE todos ADD COLUMN due TEXT;
+    CREATE INDEX todos_due ON todos (due);",
+    // comma-separated minutes
+    "ALTER TABLE todos ADD COLUMN remind_before TEXT NOT NULL DEFAULT '';",
+    // an RRULE
+    "ALTER TABLE todos ADD COLUMN recurrence TEXT;",
+    // 0 = low, 1 = medium, 2 = high
+    "ALTER TABLE todos ADD COLUMN priority INTEGER NOT NULL DEFAULT 1;",
+    "CREATE TABLE todo_tags (
+        todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (todo_id, tag)
+    );
+    CREATE INDEX todo_tags_tag ON todo_tags (tag);",
+    "ALTER TABLE todos ADD COLUMN notes TEXT NOT NULL DEFAULT '';",
+    "ALTER TABLE todos ADD COLUMN parent_id INTEGER;
+    CREATE INDEX todos_parent_id ON todos (parent_id);",
+    // deferred so a list can be loaded in any order, such as a todo before the ones it depends on
+    "CREATE TABLE todo_dependencies (
+        todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        depends_on INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (todo_id, depends_on)
+    );
+    CREATE INDEX todo_dependencies_depends_on ON todo_dependencies (depends_on);",
+    // UTC, and NULL for todos from before they were recorded
+    "ALTER TABLE todos ADD COLUMN created_at TEXT;
+    ALTER TABLE todos ADD COLUMN updated_at TEXT;
+    ALTER TABLE todos ADD COLUMN completed_at TEXT;",
+    // NULL for the default list; named lists are kept in order of creation even while empty
+    "ALTER TABLE todos ADD COLUMN list TEXT;
+    CREATE INDEX todos_list ON todos (list);
+    CREATE TABLE lists (name TEXT PRIMARY KEY);",
+];
+
+const TODO_COLUMNS: &str = "id, name, completed, revision, due, remind_before, recurrence, priority, notes, parent_id,
+    created_at, updated_at, completed_at,
+    (SELECT group_concat(tag, char(31) ORDER BY position) FROM todo_tags WHERE todo_id = todos.id),
+    (SELECT group_concat(depends_on, ',' ORDER BY position) FROM todo_dependencies WHERE todo_id = todos.id),
+    list";
+
+/// Separates tags in the aggregated tag column.
+const TAG_SEPARATOR: char = '\u{1f}';
+
+/// Stores todos in a SQLite database.
+#[derive(Debug)]
+pub struct SqliteTodoRepository {
+    path: PathBuf,
+    connection: Connection,
+}
+
+impl SqliteTodoRepository {
+    /// Opens (creating if needed) the database at `path` and brings its schema up to date.
+    pub fn open(path: impl Into<PathBuf>) -> Result<SqliteTodoRepository, NudgelystError> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(&path)?;
+        // let concurrent nudgelyst processes wait on each other rather than fail
+        connection.busy_timeout(Duration::from_secs(5))?;
+        // so deleting a todo deletes its tags
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrate(&connection)?;
+
+        Ok(SqliteTodoRepository { path, connection })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// One-shot import of an existing YAML list, keeping every todo's id along
+    /// with the list's id high-water mark. Refuses to run against a database that
+    /// already has todos. Returns how many todos were imported.
+    pub fn import_from_file(&self, file_repo: &FileTodoRepository) -> Result<usize, NudgelystError> {
+        let store = file_repo.read_from_file()?;
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let existing: usize = transaction.query_row("SELECT COUNT(*) FROM todos", [], |row| row.get(0))?;
+        if existing > 0 {
+            return Err(NudgelystError::Conflict(format!("{} already has todos; refusing to import over them", self.path.display())));
+        }
+
+        for todo in &store.todos {
+            insert_todo(&transaction, todo)?;
+        }
+        for list in &store.lists {
+            transaction.execute("INSERT OR IGNORE INTO lists (name) VALUES (?1)", params![list])?;
+        }
+        transaction.execute("DELETE FROM sqlite_sequence WHERE name = 'todos'", [])?;
+        transaction.execute(
+            "INSERT INTO sqlite_sequence (name, seq) VALUES ('todos', ?1)",
+            params![store.last_id as i64],
+        )?;
+        transaction.commit()?;
+
+        Ok(store.todos.len())
+    }
+
+    /// `filter` is a WHERE clause whose parameters start at `?2`.
+    fn query_todos(&self, filter: &str, filter_params: &[&dyn ToSql], limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        let sql = format!("SELECT {} FROM todos {} ORDER BY id LIMIT ?1", TODO_COLUMNS, filter);
+        // a negative LIMIT means no limit in SQLite
+        let limit = limit.map(|limit| limit as i64).unwrap_or(-1);
+        let mut all_params: Vec<&dyn ToSql> = vec![&limit];
+        all_params.extend_from_slice(filter_params);
+
+        let mut statement = self.connection.prepare(&sql)?;
+        let todos = statement
+            .query_map(all_params.as_slice(), todo_from_row)?
+            .collect::<Result<Vec<Todo>, _>>()?;
+
+        Ok(todos)
+    }
+
+    fn list_names(&self) -> Result<Vec<String>, NudgelystError> {
+        let mut statement = self.connection.prepare("SELECT name FROM lists ORDER BY rowid")?;
+        let names = statement.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+
+        Ok(names)
+    }
+
+    fn has_list(&self, name: &str) -> Result<bool, NudgelystError> {
+        Ok(self.get_lists()?.iter().any(|list| list.name == name))
+    }
+
+    /// Makes sure `name` is a list that can be renamed or deleted.
+    fn check_named_list(&self, name: &str) -> Result<(), NudgelystError> {
+        if name == DEFAULT_LIST {
+            return Err(NudgelystError::InvalidInput(format!("the `{}` list can't be renamed or deleted", DEFAULT_LIST)));
+        }
+        if !self.has_list(name)? {
+            return Err(NudgelystError::no_list(name));
+        }
+
+        Ok(())
+    }
+}
+
+fn migrate(connection: &Connection) -> Result<(), NudgelystError> {
+    let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.unchecked_transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+fn todo_from_row(row: &Row) -> rusqlite::Result<Todo> {
+    Ok(Todo {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        completed: row.get(2)?,
+        revision: row.get(3)?,
+        due: row.get::<_, Option<DateTime<Utc>>>(4)?.map(|due| due.with_timezone(&Local)),
+        remind_before: split_minutes(&row.get::<_, String>(5)?),
+        recurrence: row
+            .get::<_, Option<String>>(6)?
+            .map(|rule| rule.parse())
+            .transpose()
+            .map_err(|err: NudgelystError| rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(err)))?,
+        priority: priority_from_rank(row.get(7)?),
+        notes: row.get(8)?,
+        parent_id: row.get::<_, Option<i64>>(9)?.map(|parent_id| parent_id as usize),
+        created_at: local(row.get(10)?),
+        updated_at: local(row.get(11)?),
+        completed_at: local(row.get(12)?),
+        tags: row
+            .get::<_, Option<String>>(13)?
+            .map(|tags| tags.split(TAG_SEPARATOR).map(String::from).collect())
+            .unwrap_or_default(),
+        depends_on: split_ids(&row.get::<_, Option<String>>(14)?.unwrap_or_default()),
+        list: row.get(15)?,
+    })
+}
+
+fn utc(moment: Option<DateTime<Local>>) -> Option<DateTime<Utc>> {
+    moment.map(|moment| moment.with_timezone(&Utc))
+}
+
+fn local(moment: Option<DateTime<Utc>>) -> Option<DateTime<Local>> {
+    moment.map(|moment| moment.with_timezone(&Local))
+}
+
+fn join_minutes(minutes: &[u64]) -> String {
+    minutes.iter().map(u64::to_string).collect::<Vec<_>>().join(",")
+}
+
+fn split_minutes(minutes: &str) -> Vec<u64> {
+    minutes.split(',').filter_map(|minutes| minutes.parse().ok()).collect()
+}
+
+fn split_ids(ids: &str) -> Vec<usize> {
+    ids.split(',').filter_map(|id| id.parse().ok()).collect()
+}
+
+fn priority_rank(priority: Priority) -> u8 {
+    match priority {
+        Priority::Low => 0,
+        Priority::Medium => 1,
+        Priority::High => 2,
+    }
+}
+
+fn priority_from_rank(rank: u8) -> Priority {
+    match rank {
+        0 => Priority::Low,
+        1 => Priority::Medium,
+        _ => Priority::High,
+    }
+}
+
+fn insert_todo(connection: &Connection, todo: &Todo) -> Result<(), NudgelystError> {
+    let mut todo = todo.clone();
+    todo.stamp_created(Local::now());
+    // NULL lets SQLite allocate the next id
+    let id = if todo.id == 0 { None } else { Some(todo.id as i64) };
+    connection.execute(
+        "INSERT INTO todos (id, name, completed, revision, due, remind_before, recurrence, priority, notes, parent_id,
+            created_at, updated_at, completed_at, list)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            id,
+            todo.name,
+            todo.completed,
+            todo.revision,
+            utc(todo.due),
+            join_minutes(&todo.remind_before),
+            todo.recurrence.as_ref().map(Recurrence::to_string),
+            priority_rank(todo.priority),
+            todo.notes,
+            todo.parent_id.map(|parent_id| parent_id as i64),
+            utc(todo.created_at),
+            utc(todo.updated_at),
+            utc(todo.completed_at),
+            todo.list,
+        ],
+    )?;
+
+    let id = connection.last_insert_rowid();
+    save_tags(connection, id, &todo.tags)?;
+    save_dependencies(connection, id, &todo.depends_on)
+}
+
+fn update_todo(connection: &Connection, todo: &Todo) -> Result<(), NudgelystError> {
+    let updated = connection.execute(
+        "UPDATE todos SET name = ?3, completed = ?4, due = ?5, remind_before = ?6, recurrence = ?7, priority = ?8,
+        notes = ?9, parent_id = ?10, updated_at = ?11,
+        completed_at = CASE WHEN ?4 THEN COALESCE(?12, completed_at, ?11) END, list = ?13, revision = revision + 1
+        WHERE id = ?1 AND revision = ?2",
+        params![
+            todo.id as i64,
+            todo.revision,
+            todo.name,
+            todo.completed,
+            utc(todo.due),
+            join_minutes(&todo.remind_before),
+            todo.recurrence.as_ref().map(Recurrence::to_string),
+            priority_rank(todo.priority),
+            todo.notes,
+            todo.parent_id.map(|parent_id| parent_id as i64),
+            Utc::now(),
+            utc(todo.completed_at),
+            todo.list,
+        ],
+    )?;
+
+    check_changed(connection, todo, updated)?;
+    save_tags(connection, todo.id as i64, &todo.tags)?;
+    save_dependencies(connection, todo.id as i64, &todo.depends_on)
+}
+
+fn save_dependencies(connection: &Connection, todo_id: i64, depends_on: &[usize]) -> Result<(), NudgelystError> {
+    connection.execute("DELETE FROM todo_dependencies WHERE todo_id = ?1", params![todo_id])?;
+    for (position, id) in depends_on.iter().enumerate() {
+        connection.execute(
+            "INSERT OR IGNORE INTO todo_dependencies (todo_id, depends_on, position) VALUES (?1, ?2, ?3)",
+            params![todo_id, *id as i64, position as i64],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn save_tags(connection: &Connection, todo_id: i64, tags: &[String]) -> Result<(), NudgelystError> {
+    connection.execute("DELETE FROM todo_tags WHERE todo_id = ?1", params![todo_id])?;
+    for (position, tag) in tags.iter().enumerate() {
+        connection.execute(
+            "INSERT OR IGNORE INTO todo_tags (todo_id, position, tag) VALUES (?1, ?2, ?3)",
+            params![todo_id, position as i64, tag],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn delete_todo(connection: &Connection, todo: &Todo) -> Result<(), NudgelystError> {
+    let deleted = connection.execute(
+        "DELETE FROM todos WHERE id = ?1 AND revision = ?2",
+        params![todo.id as i64, todo.revision],
+    )?;
+
+    check_changed(connection, todo, deleted)
+}
+
+/// Works out why a write guarded on id and revision didn't touch any rows.
+fn check_changed(connection: &Connection, todo: &Todo, changed: usize) -> Result<(), NudgelystError> {
+    if changed > 0 {
+        return Ok(());
+    }
+
+    let stored_revision: Option<u64> = connection
+        .query_row("SELECT revision FROM todos WHERE id = ?1", params![todo.id as i64], |row| row.get(0))
+        .optional()?;
+
+    match stored_revision {
+        None => Err(NudgelystError::not_found_id(todo.id)),
+        Some(stored_revision) => Err(NudgelystError::stale_revision(todo.id, stored_revision, todo.revision)),
+    }
+}
+
+impl TodoRepository for SqliteTodoRepository {
+    fn get_todo(&self, id: usize) -> Result<Todo, NudgelystError> {
+        let sql = format!("SELECT {} FROM todos WHERE id = ?1", TODO_COLUMNS);
+
+        self.connection
+            .query_row(&sql, params![id as i64], todo_from_row)
+            .optional()?
+            .ok_or_else(|| NudgelystError::not_found_id(id))
+    }
+
+    fn get_todo_by_name(&self, name: String) -> Result<Todo, NudgelystError> {
+        let sql = format!("SELECT {} FROM todos WHERE name = ?1 ORDER BY id LIMIT 1", TODO_COLUMNS);
+
+        self.connection
+            .query_row(&sql, params![&name], todo_from_row)
+            .optional()?
+            .ok_or_else(|| NudgelystError::not_found_name(name))
+    }
+
+    fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.query_todos("", &[], limit)
+    }
+
+    fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.query_todos("WHERE completed = 1", &[], limit)
+    }
+
+    fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.query_todos("WHERE completed = 0", &[], limit)
+    }
+
+    fn get_todos_with_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        let filter = "WHERE id IN (SELECT todo_id FROM todo_tags WHERE tag = ?2)";
+        self.query_todos(filter, &[&normalize_tag(&tag)], limit)
+    }
+
+    fn get_todos_without_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        let filter = "WHERE id NOT IN (SELECT todo_id FROM todo_tags WHERE tag = ?2)";
+        self.query_todos(filter, &[&normalize_tag(&tag)], limit)
+    }
+
+    fn get_tag_counts(&self) -> Result<BTreeMap<String, usize>, NudgelystError> {
+        let mut statement = self.connection.prepare("SELECT tag, COUNT(*) FROM todo_tags GROUP BY tag")?;
+        let counts = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<BTreeMap<String, usize>, _>>()?;
+
+        Ok(counts)
+    }
+
+    fn get_todos_in_list(&self, list: &str, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+        self.query_todos("WHERE list IS ?2", &[&list_field(list)], limit)
+    }
+
+    fn get_lists(&self) -> Result<Vec<TodoList>, NudgelystError> {
+        let mut statement = self.connection.prepare("SELECT list, COUNT(*), SUM(completed) FROM todos GROUP BY list")?;
+        let counts = statement
+            .query_map([], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, usize>(1)?, row.get::<_, usize>(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut lists = count_lists(&self.list_names()?, &[]);
+        for (list, todos, completed) in counts {
+            let name = list.as_deref().unwrap_or(DEFAULT_LIST);
+            match lists.iter_mut().find(|existing| existing.name == name) {
+                Some(existing) => {
+                    existing.todos = todos;
+                    existing.completed = completed;
+                },
+                None => lists.push(TodoList { name: name.to_string(), todos, completed }),
+            }
+        }
+
+        Ok(lists)
+    }
+
+    fn create_list(&self, name: &str) -> Result<(), NudgelystError> {
+        validate_list_name(name)?;
+        if self.has_list(name)? {
+            return Err(NudgelystError::Conflict(format!("there's already a list named `{}`", name)));
+        }
+
+        self.connection.execute("INSERT INTO lists (name) VALUES (?1)", params![name])?;
+
+        Ok(())
+    }
+
+    fn rename_list(&self, from: &str, to: &str) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        self.check_named_list(from)?;
+        validate_list_name(to)?;
+        if self.has_list(to)? {
+            return Err(NudgelystError::Conflict(format!("there's already a list named `{}`", to)));
+        }
+
+        transaction.execute("INSERT OR IGNORE INTO lists (name) VALUES (?1)", params![from])?;
+        transaction.execute("UPDATE lists SET name = ?2 WHERE name = ?1", params![from, to])?;
+        transaction.execute("UPDATE todos SET list = ?2, revision = revision + 1 WHERE list = ?1", params![from, to])?;
+
+        Ok(transaction.commit()?)
+    }
+
+    fn delete_list(&self, name: &str) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        self.check_named_list(name)?;
+
+        transaction.execute("DELETE FROM todos WHERE list = ?1", params![name])?;
+        transaction.execute("DELETE FROM lists WHERE name = ?1", params![name])?;
+
+        Ok(transaction.commit()?)
+    }
+
+    fn move_todos(&self, todos: Vec<Todo>, to: &str) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        if !self.has_list(to)? {
+            return Err(NudgelystError::no_list(to));
+        }
+
+        let moving: HashSet<usize> = todos.iter().map(|todo| todo.id).collect();
+        let now = Utc::now();
+        for todo in &todos {
+            let parent_id = todo.parent_id.filter(|parent_id| moving.contains(parent_id)).map(|parent_id| parent_id as i64);
+            let moved = transaction.execute(
+                "UPDATE todos SET list = ?3, parent_id = ?4, updated_at = ?5, revision = revision + 1 WHERE id = ?1 AND revision = ?2",
+                params![todo.id as i64, todo.revision, list_field(to), parent_id, now],
+            )?;
+            check_changed(&transaction, todo, moved)?;
+        }
+
+        Ok(transaction.commit()?)
+    }
+
+    fn create_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        insert_todo(&transaction, &todo)?;
+
+        Ok(transaction.commit()?)
+    }
+
+    fn create_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        for todo in &todos {
+            insert_todo(&transaction, todo)?;
+        }
+
+        Ok(transaction.commit()?)
+    }
+
+    fn update_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        update_todo(&transaction, &todo)?;
+
+        Ok(transaction.commit()?)
+    }
+
+    fn update_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        for todo in &todos {
+            update_todo(&transaction, todo)?;
+        }
+
+        Ok(transaction.commit()?)
+    }
+
+    fn delete_todo(&self, todo: Todo) -> Result<(), NudgelystError> {
+        delete_todo(&self.connection, &todo)
+    }
+
+    fn delete_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        for todo in &todos {
+            delete_todo(&transaction, todo)?;
+        }
+
+        Ok(transaction.commit()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::TodoRef;
+    use chrono::TimeZone;
+
+    fn open_repo(dir: &tempfile::TempDir) -> SqliteTodoRepository {
+        SqliteTodoRepository::open(dir.path().join("nudgelyst.db")).unwrap()
+    }
+
+    /// Clears the timestamps stamped on creation so todos can be compared whole.
+    fn unstamped(todos: Vec<Todo>) -> Vec<Todo> {
+        todos.into_iter().map(|todo| Todo { created_at: None, updated_at: None, ..todo }).collect()
+    }
+
+    #[test]
+    fn test_create_and_get_todos() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+
+        // Act
+        let result = repo.create_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo::new(0, "Task 2".to_string()),
+        ]);
+
+        // Assert
+        match result {
+            Ok(()) => {
+                assert_eq!(unstamped(repo.get_todos(None).unwrap()), vec![
+                    Todo::new(1, "Task 1".to_string()),
+                    Todo::new(2, "Task 2".to_string()),
+                ]);
+                assert_eq!(repo.get_todos(Some(1)).unwrap().len(), 1);
+                assert_eq!(repo.get_todo_by_name("Task 2".to_string()).unwrap().id, 2);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_todo_not_found() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+
+        // Act
+        let result = repo.get_todo(999);
+
+        // Assert
+        match result {
+            Ok(_) => {
+                panic!("Expected error, but got Ok");
+            }
+            Err(err) => {
+                assert!(matches!(err, NudgelystError::NotFound(TodoRef::Id(999))));
+            }
+        }
+    }
+
+    #[test]
+    fn test_completed_and_incomplete_todos() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo::new(0, "Task 2".to_string()),
+        ]).unwrap();
+        repo.update_todo(Todo { completed: true, ..repo.get_todo(2).unwrap() }).unwrap();
+
+        // Act
+        let completed = repo.get_completed_todos(None).unwrap();
+        let incomplete = repo.get_incomplete_todos(None).unwrap();
+
+        // Assert
+        assert_eq!(completed.iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(incomplete.iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_update_todo_bumps_revision() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+
+        // Act
+        let result = repo.update_todo(Todo { completed: true, ..repo.get_todo(1).unwrap() });
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(repo.get_todo(1).unwrap().revision, 1);
+    }
+
+    #[test]
+    fn test_update_todo_schedule() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+        let due = Local.with_ymd_and_hms(2024, 5, 3, 17, 0, 0).unwrap();
+
+        // Act
+        let result = repo.update_todo(Todo {
+            due: Some(due),
+            remind_before: vec![60, 15],
+            recurrence: Some("every 2 weeks".parse().unwrap()),
+            priority: Priority::High,
+            notes: "Check the rota first\nthen swap".to_string(),
+            parent_id: Some(1),
+            ..repo.get_todo(1).unwrap()
+        });
+
+        // Assert
+        assert!(result.is_ok());
+        let todo = repo.get_todo(1).unwrap();
+        assert_eq!(todo.due, Some(due));
+        assert_eq!(todo.remind_before, vec![60, 15]);
+        assert_eq!(todo.recurrence.unwrap().to_string(), "FREQ=WEEKLY;INTERVAL=2");
+        assert_eq!(todo.priority, Priority::High);
+        assert_eq!(todo.notes, "Check the rota first\nthen swap");
+        assert_eq!(todo.parent_id, Some(1));
+    }
+
+    #[test]
+    fn test_deleting_a_todo_drops_dependencies_on_it() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        // loaded before the todos it depends on
+        repo.create_todos(vec![
+            Todo { depends_on: vec![3, 2], ..Todo::new(1, "Build it".to_string()) },
+            Todo::new(2, "Write design doc".to_string()),
+            Todo::new(3, "Get design reviewed".to_string()),
+        ]).unwrap();
+        assert_eq!(repo.get_todo(1).unwrap().depends_on, vec![3, 2]);
+
+        // Act
+        let result = repo.delete_todo(repo.get_todo(3).unwrap());
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(repo.get_todo(1).unwrap().depends_on, vec![2]);
+    }
+
+    #[test]
+    fn test_lists() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_list("work").unwrap();
+        repo.create_list("home").unwrap();
+        repo.create_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo { list: list_field("work"), completed: true, ..Todo::new(0, "Task 2".to_string()) },
+        ]).unwrap();
+
+        // Act
+        repo.move_todos(vec![repo.get_todo(1).unwrap()], "work").unwrap();
+        repo.rename_list("work", "job").unwrap();
+        repo.delete_list("home").unwrap();
+
+        // Assert
+        assert_eq!(repo.get_lists().unwrap(), vec![
+            TodoList { name: DEFAULT_LIST.to_string(), todos: 0, completed: 0 },
+            TodoList { name: "job".to_string(), todos: 2, completed: 1 },
+        ]);
+        assert_eq!(repo.get_todos_in_list("job", None).unwrap().len(), 2);
+        assert_eq!(repo.get_todo(1).unwrap().revision, 2);
+        assert!(matches!(repo.delete_list(DEFAULT_LIST), Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(repo.create_list("job"), Err(NudgelystError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_depending_on_a_missing_todo() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+
+        // Act
+        let result = repo.update_todo(Todo { depends_on: vec![7], ..repo.get_todo(1).unwrap() });
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert!(repo.get_todo(1).unwrap().depends_on.is_empty());
+    }
+
+    #[test]
+    fn test_timestamps() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        let before = Local::now();
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+        let created = repo.get_todo(1).unwrap();
+
+        // Act
+        let result = repo.update_todo(Todo { completed: true, created_at: None, ..created.clone() });
+
+        // Assert
+        assert!(result.is_ok());
+        let completed = repo.get_todo(1).unwrap();
+        let created_at = created.created_at.unwrap();
+        assert!(before <= created_at && created_at <= Local::now());
+        assert_eq!(created.updated_at, created.created_at);
+        assert_eq!(created.completed_at, None);
+        assert_eq!(completed.created_at, created.created_at);
+        assert!(completed.updated_at.unwrap() >= created_at);
+        assert!(completed.completed_at.is_some());
+        repo.update_todo(Todo { completed: false, ..completed }).unwrap();
+        assert_eq!(repo.get_todo(1).unwrap().completed_at, None);
+    }
+
+    #[test]
+    fn test_tag_queries() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todos(vec![
+            Todo { tags: vec!["work".to_string(), "urgent".to_string()], ..Todo::new(0, "Fix CI".to_string()) },
+            Todo { tags: vec!["work".to_string()], ..Todo::new(0, "Review PR".to_string()) },
+            Todo::new(0, "Buy milk".to_string()),
+        ]).unwrap();
+        repo.delete_todo(repo.get_todo(2).unwrap()).unwrap();
+
+        // Act
+        let with_work = repo.get_todos_with_tag("+Work".to_string(), None);
+        let without_work = repo.get_todos_without_tag("work".to_string(), None);
+        let counts = repo.get_tag_counts();
+
+        // Assert
+        assert_eq!(with_work.unwrap()[0].tags, vec!["work", "urgent"]);
+        assert_eq!(without_work.unwrap().iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(counts.unwrap(), BTreeMap::from([("urgent".to_string(), 1), ("work".to_string(), 1)]));
+    }
+
+    #[test]
+    fn test_update_todo_missing() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+
+        // Act
+        let result = repo.update_todo(Todo::new(1, "Task 1".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::NotFound(TodoRef::Id(1)))));
+    }
+
+    #[test]
+    fn test_delete_todos_stale_revision_rolls_back() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo::new(0, "Task 2".to_string()),
+        ]).unwrap();
+        let stale = repo.get_todos(None).unwrap();
+        repo.update_todo(Todo { completed: true, ..repo.get_todo(2).unwrap() }).unwrap();
+
+        // Act
+        let result = repo.delete_todos(stale);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_ids_are_not_reused_after_delete() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+        repo.delete_todos(repo.get_todos(None).unwrap()).unwrap();
+
+        // Act
+        repo.create_todo(Todo::new(0, "Task 2".to_string())).unwrap();
+
+        // Assert
+        assert_eq!(unstamped(repo.get_todos(None).unwrap()), vec![Todo::new(2, "Task 2".to_string())]);
+    }
+
+    #[test]
+    fn test_import_from_file_keeps_ids() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let file_repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        file_repo.create_todos(vec![
+            Todo::new(0, "Task 1".to_string()),
+            Todo::new(0, "Task 2".to_string()),
+            Todo::new(0, "Task 3".to_string()),
+        ]).unwrap();
+        file_repo.delete_todo(file_repo.get_todo(3).unwrap()).unwrap();
+        let repo = open_repo(&dir);
+
+        // Act
+        let result = repo.import_from_file(&file_repo);
+
+        // Assert
+        match result {
+            Ok(imported) => {
+                assert_eq!(imported, 2);
+                assert_eq!(repo.get_todos(None).unwrap(), file_repo.get_todos(None).unwrap());
+                repo.create_todo(Todo::new(0, "Task 4".to_string())).unwrap();
+                assert_eq!(repo.get_todo_by_name("Task 4".to_string()).unwrap().id, 4);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_import_from_file_refuses_non_empty_database() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let file_repo = FileTodoRepository::new(dir.path().join("nudgelyst.yaml"));
+        file_repo.create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+        let repo = open_repo(&dir);
+        repo.create_todo(Todo::new(0, "Existing".to_string())).unwrap();
+
+        // Act
+        let result = repo.import_from_file(&file_repo);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_reopen_keeps_schema() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        open_repo(&dir).create_todo(Todo::new(0, "Task 1".to_string())).unwrap();
+
+        // Act
+        let repo = open_repo(&dir);
+
+        // Assert
+        assert_eq!(repo.get_todos(None).unwrap().len(), 1);
+    }
+}
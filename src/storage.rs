@@ -0,0 +1,72 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use crate::error::NudgelystError;
+use crate::file_todo_repository::FileTodoRepository;
+use crate::todo::TodoRepository;
+
+#[cfg(not(feature = "sqlite"))]
+const NO_SQLITE_SUPPORT: &str = "nudgelyst was built without SQLite support; rebuild it with `--features sqlite`";
+
+/// The storage engines a todo list can live in.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A single YAML file
+    #[default]
+    Yaml,
+    /// A SQLite database (requires the `sqlite` feature)
+    Sqlite,
+}
+
+impl Backend {
+    /// The file name used for a list stored with this backend.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Backend::Yaml => "nudgelyst.yaml",
+            Backend::Sqlite => "nudgelyst.db",
+        }
+    }
+}
+
+/// Opens the todo list at `path` with the given backend.
+pub fn open_repository(backend: Backend, path: PathBuf) -> Result<Box<dyn TodoRepository>, NudgelystError> {
+    match backend {
+        Backend::Yaml => Ok(Box::new(FileTodoRepository::new(path))),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Ok(Box::new(crate::sqlite_todo_repository::SqliteTodoRepository::open(path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => Err(NudgelystError::InvalidInput(NO_SQLITE_SUPPORT.to_string())),
+    }
+}
+
+/// Creates a new, empty todo list at `path`, failing if one already exists.
+pub fn init_repository(backend: Backend, path: &Path) -> Result<(), NudgelystError> {
+    match backend {
+        Backend::Yaml => FileTodoRepository::new(path).init(),
+        Backend::Sqlite => {
+            if path.exists() {
+                return Err(NudgelystError::Conflict(format!("{} already exists", path.display())));
+            }
+
+            open_repository(backend, path.to_path_buf()).map(|_| ())
+        }
+    }
+}
+
+/// Copies the YAML list at `yaml_path` into a new SQLite list at `sqlite_path`,
+/// keeping todo ids. Returns how many todos were imported.
+#[cfg(feature = "sqlite")]
+pub fn import_into_sqlite(yaml_path: &Path, sqlite_path: &Path) -> Result<usize, NudgelystError> {
+    if !yaml_path.exists() {
+        return Err(NudgelystError::InvalidInput(format!("{} does not exist", yaml_path.display())));
+    }
+
+    crate::sqlite_todo_repository::SqliteTodoRepository::open(sqlite_path)?
+        .import_from_file(&FileTodoRepository::new(yaml_path))
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn import_into_sqlite(_yaml_path: &Path, _sqlite_path: &Path) -> Result<usize, NudgelystError> {
+    Err(NudgelystError::InvalidInput(NO_SQLITE_SUPPORT.to_string()))
+}
@@ -0,0 +1,425 @@
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::error::NudgelystError;
+use crate::todo::{complete_todo, Todo, TodoRepository};
+
+/// What `todone` does with a todo that still has open subtasks.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletePolicy {
+    /// Leave the todo open until its subtasks are done
+    #[default]
+    Refuse,
+    /// Complete the open subtasks too
+    Cascade,
+    /// Complete the todo and leave its subtasks open
+    Warn,
+}
+
+/// What happens to the subtasks of todos that are removed.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanPolicy {
+    /// Keep todos that have subtasks
+    #[default]
+    Refuse,
+    /// Remove the subtasks as well
+    Delete,
+    /// Move the subtasks up to the removed todo's own parent
+    Promote,
+}
+
+/// How many of a todo's direct subtasks are done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rollup {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// The outcome of completing a todo with `complete_with_subtasks`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Completion {
+    /// The next occurrence of a recurring todo.
+    pub next: Option<Todo>,
+    /// Open subtasks that were completed along with the todo.
+    pub also_completed: Vec<Todo>,
+    /// Open subtasks that were left open.
+    pub left_open: Vec<Todo>,
+}
+
+/// Counts done and total direct subtasks for every todo in `todos` that has any.
+pub fn rollups(todos: &[Todo]) -> HashMap<usize, Rollup> {
+    let mut rollups: HashMap<usize, Rollup> = HashMap::new();
+    for todo in todos {
+        if let Some(parent_id) = todo.parent_id {
+            let rollup = rollups.entry(parent_id).or_default();
+            rollup.total += 1;
+            rollup.done += usize::from(todo.completed);
+        }
+    }
+
+    rollups
+}
+
+/// Arranges `todos` into a tree, pairing each with its depth. Subtasks follow their
+/// parent in the order they were given in, and todos whose parent isn't in `todos`
+/// are shown at the top level.
+pub fn tree(todos: Vec<Todo>) -> Vec<(usize, Todo)> {
+    let ids: HashSet<usize> = todos.iter().map(|todo| todo.id).collect();
+    let mut children: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    let mut roots = Vec::new();
+    for (index, todo) in todos.iter().enumerate() {
+        match todo.parent_id.filter(|parent_id| ids.contains(parent_id) && *parent_id != todo.id) {
+            Some(parent_id) => children.entry(parent_id).or_default().push(index),
+            None => roots.push(index),
+        }
+    }
+
+    let mut order = Vec::with_capacity(todos.len());
+    let mut visited = HashSet::new();
+    let mut stack: Vec<(usize, usize)> = roots.into_iter().rev().map(|index| (0, index)).collect();
+    while let Some((depth, index)) = stack.pop() {
+        if !visited.insert(index) {
+            continue;
+        }
+        order.push((depth, index));
+        if let Some(child_indexes) = children.get(&todos[index].id) {
+            stack.extend(child_indexes.iter().rev().map(|child| (depth + 1, *child)));
+        }
+    }
+
+    let mut todos: Vec<Option<Todo>> = todos.into_iter().map(Some).collect();
+    // todos caught in a parent cycle never hang off a root, so they go last at the top level
+    let leftovers: Vec<usize> = (0..todos.len()).filter(|index| !visited.contains(index)).collect();
+    order
+        .into_iter()
+        .chain(leftovers.into_iter().map(|index| (0, index)))
+        .filter_map(|(depth, index)| todos[index].take().map(|todo| (depth, todo)))
+        .collect()
+}
+
+/// Every subtask of `id` among `todos`, however deeply nested.
+pub fn descendants(todos: &[Todo], id: usize) -> Vec<Todo> {
+    let mut found: Vec<Todo> = Vec::new();
+    let mut parents = vec![id];
+    while let Some(parent_id) = parents.pop() {
+        for todo in todos.iter().filter(|todo| todo.parent_id == Some(parent_id)) {
+            if todo.id != id && !found.iter().any(|found| found.id == todo.id) {
+                parents.push(todo.id);
+                found.push(todo.clone());
+            }
+        }
+    }
+
+    found
+}
+
+/// Checks that `todo_id` can be moved under `parent_id`: the parent has to exist and
+/// can't be the todo itself or one of its subtasks.
+pub fn check_parent(repo: &dyn TodoRepository, todo_id: usize, parent_id: usize) -> Result<(), NudgelystError> {
+    repo.get_todo(parent_id)?;
+    let all = repo.get_todos(None)?;
+    if parent_id == todo_id || descendants(&all, todo_id).iter().any(|todo| todo.id == parent_id) {
+        return Err(NudgelystError::InvalidInput(format!(
+            "todo {} can't go under {}, that would make it its own subtask",
+            todo_id, parent_id
+        )));
+    }
+
+    Ok(())
+}
+
+/// Completes `todo`, dealing with any open subtasks according to `policy`.
+pub fn complete_with_subtasks(repo: &dyn TodoRepository, todo: Todo, policy: CompletePolicy, now: DateTime<Local>)
+    -> Result<Completion, NudgelystError> {
+    let all = repo.get_todos(None)?;
+    let open: Vec<Todo> = descendants(&all, todo.id).into_iter().filter(|todo| !todo.completed).collect();
+
+    let mut completion = Completion::default();
+    match policy {
+        CompletePolicy::Refuse if !open.is_empty() => {
+            return Err(NudgelystError::Conflict(format!(
+                "todo {} has {} open subtask(s); finish them first or pass --children cascade or --children warn",
+                todo.id,
+                open.len()
+            )));
+        }
+        CompletePolicy::Cascade => {
+            for subtask in &open {
+                complete_todo(repo, subtask.clone(), now)?;
+            }
+            completion.also_completed = open;
+        }
+        _ => completion.left_open = open,
+    }
+    completion.next = complete_todo(repo, todo, now)?;
+
+    Ok(completion)
+}
+
+/// Deletes `todos`, dealing with their subtasks according to `policy`. Returns the
+/// todos that were deleted, subtasks included.
+pub fn remove_with_subtasks(repo: &dyn TodoRepository, todos: Vec<Todo>, policy: OrphanPolicy) -> Result<Vec<Todo>, NudgelystError> {
+    let all = repo.get_todos(None)?;
+    let mut removed = todos;
+    if policy == OrphanPolicy::Delete {
+        let subtasks: Vec<Todo> = removed.iter().flat_map(|todo| descendants(&all, todo.id)).collect();
+        for subtask in subtasks {
+            if !removed.iter().any(|todo| todo.id == subtask.id) {
+                removed.push(subtask);
+            }
+        }
+    }
+
+    let removed_ids: HashSet<usize> = removed.iter().map(|todo| todo.id).collect();
+    let parents: HashMap<usize, Option<usize>> = all.iter().map(|todo| (todo.id, todo.parent_id)).collect();
+    let orphan_ids: HashSet<usize> = all
+        .iter()
+        .filter(|todo| !removed_ids.contains(&todo.id))
+        .filter(|todo| todo.parent_id.is_some_and(|parent_id| removed_ids.contains(&parent_id)))
+        .map(|todo| todo.id)
+        .collect();
+    let orphans = orphan_ids.len();
+
+    if orphans > 0 && policy == OrphanPolicy::Refuse {
+        return Err(NudgelystError::Conflict(format!(
+            "{} subtask(s) would be left without a parent; pass --children delete or --children promote",
+            orphans
+        )));
+    }
+
+    // deleted before promoting, so a failed delete leaves everything as it was; a
+    // subtask whose parent is gone already shows at the top level
+    repo.delete_todos(removed.clone())?;
+
+    if orphans > 0 {
+        // re-read, as deleting dropped the orphans' dependencies on the removed todos
+        let promoted = repo
+            .get_todos(None)?
+            .into_iter()
+            .filter(|todo| orphan_ids.contains(&todo.id))
+            .map(|orphan| {
+                // climb to the closest ancestor that is staying, giving up after
+                // as many steps as there are todos in case the parents loop
+                let mut parent_id = orphan.parent_id;
+                for _ in 0..parents.len() {
+                    match parent_id {
+                        Some(id) if removed_ids.contains(&id) => parent_id = parents.get(&id).copied().flatten(),
+                        _ => break,
+                    }
+                }
+                Todo { parent_id: parent_id.filter(|id| !removed_ids.contains(id)), ..orphan }
+            })
+            .collect();
+        repo.update_todos(promoted)?;
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::in_memory_todo_repository::InMemoryTodoRepository;
+
+    fn subtask(name: &str, parent_id: usize) -> Todo {
+        Todo { parent_id: Some(parent_id), ..Todo::new(0, name.to_string()) }
+    }
+
+    /// 1 Move house
+    ///   2 Pack
+    ///     4 Pack books
+    ///   3 Book van (done)
+    /// 5 Buy milk
+    fn repo() -> InMemoryTodoRepository {
+        InMemoryTodoRepository::with_todos(vec![
+            Todo::new(0, "Move house".to_string()),
+            subtask("Pack", 1),
+            Todo { completed: true, ..subtask("Book van", 1) },
+            subtask("Pack books", 2),
+            Todo::new(0, "Buy milk".to_string()),
+        ]).unwrap()
+    }
+
+    fn ids(todos: &[Todo]) -> Vec<usize> {
+        todos.iter().map(|todo| todo.id).collect()
+    }
+
+    #[test]
+    fn test_tree() {
+        // Arrange
+        let todos = repo().get_todos(None).unwrap();
+
+        // Act
+        let tree: Vec<(usize, usize)> = tree(todos).into_iter().map(|(depth, todo)| (depth, todo.id)).collect();
+
+        // Assert
+        assert_eq!(tree, vec![(0, 1), (1, 2), (2, 4), (1, 3), (0, 5)]);
+    }
+
+    #[test]
+    fn test_tree_keeps_todos_in_a_cycle() {
+        // Arrange
+        let todos = vec![
+            Todo { parent_id: Some(2), ..Todo::new(1, "a".to_string()) },
+            Todo { parent_id: Some(1), ..Todo::new(2, "b".to_string()) },
+        ];
+
+        // Act
+        let tree = tree(todos);
+
+        // Assert
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_rollups() {
+        // Act
+        let rollups = rollups(&repo().get_todos(None).unwrap());
+
+        // Assert
+        assert_eq!(rollups[&1], Rollup { done: 1, total: 2 });
+        assert_eq!(rollups[&2], Rollup { done: 0, total: 1 });
+        assert!(!rollups.contains_key(&5));
+    }
+
+    #[test]
+    fn test_check_parent_rejects_cycles() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = check_parent(&repo, 2, 4);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::InvalidInput(_))));
+        assert!(check_parent(&repo, 4, 5).is_ok());
+        assert!(matches!(check_parent(&repo, 4, 9), Err(NudgelystError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_complete_refuses_with_open_subtasks() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = complete_with_subtasks(&repo, repo.get_todo(1).unwrap(), CompletePolicy::Refuse, Local::now());
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert!(!repo.get_todo(1).unwrap().completed);
+    }
+
+    #[test]
+    fn test_complete_cascades_to_open_subtasks() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = complete_with_subtasks(&repo, repo.get_todo(1).unwrap(), CompletePolicy::Cascade, Local::now());
+
+        // Assert
+        match result {
+            Ok(completion) => {
+                assert_eq!(ids(&completion.also_completed), vec![2, 4]);
+                assert!(repo.get_todos(None).unwrap().iter().filter(|todo| todo.id != 5).all(|todo| todo.completed));
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_complete_warns_about_open_subtasks() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = complete_with_subtasks(&repo, repo.get_todo(1).unwrap(), CompletePolicy::Warn, Local::now());
+
+        // Assert
+        match result {
+            Ok(completion) => {
+                assert_eq!(ids(&completion.left_open), vec![2, 4]);
+                assert!(repo.get_todo(1).unwrap().completed);
+                assert!(!repo.get_todo(2).unwrap().completed);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_refuses_to_orphan_subtasks() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = remove_with_subtasks(&repo, vec![repo.get_todo(2).unwrap()], OrphanPolicy::Refuse);
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(repo.get_todos(None).unwrap().len(), 5);
+        assert!(remove_with_subtasks(&repo, vec![repo.get_todo(5).unwrap()], OrphanPolicy::Refuse).is_ok());
+    }
+
+    #[test]
+    fn test_remove_deletes_subtasks() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = remove_with_subtasks(&repo, vec![repo.get_todo(1).unwrap()], OrphanPolicy::Delete);
+
+        // Assert
+        match result {
+            Ok(removed) => {
+                assert_eq!(ids(&removed), vec![1, 2, 3, 4]);
+                assert_eq!(ids(&repo.get_todos(None).unwrap()), vec![5]);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_promotes_subtasks() {
+        // Arrange
+        let repo = repo();
+
+        // Act
+        let result = remove_with_subtasks(&repo, vec![repo.get_todo(2).unwrap()], OrphanPolicy::Promote);
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(repo.get_todo(4).unwrap().parent_id, Some(1));
+    }
+
+    #[test]
+    fn test_remove_promotes_subtasks_depending_on_removed_todos() {
+        // Arrange
+        let repo = repo();
+        let book_van = repo.get_todo(3).unwrap();
+        repo.update_todo(Todo { depends_on: vec![5], ..book_van }).unwrap();
+
+        // Act
+        let result = remove_with_subtasks(&repo, vec![repo.get_todo(1).unwrap(), repo.get_todo(5).unwrap()], OrphanPolicy::Promote);
+
+        // Assert
+        match result {
+            Ok(_) => {
+                let book_van = repo.get_todo(3).unwrap();
+                assert_eq!(book_van.parent_id, None);
+                assert!(book_van.depends_on.is_empty());
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use colored::{Color, ColoredString, Colorize};
+use serde::{Deserialize, Serialize};
+use std::env;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use crate::color::Theme;
+use crate::dependencies::blockers;
+use crate::due::DUE_FORMAT;
+use crate::subtasks::{rollups, tree};
+use crate::todo::{Priority, Todo};
+
+/// The columns `list` can show.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Id,
+    /// done, open, overdue or blocked
+    Status,
+    Priority,
+    Due,
+    /// Indented under its parent when it's a subtask
+    Name,
+    Tags,
+    /// How many subtasks are done
+    Progress,
+    Repeats,
+    /// The open todos it depends on
+    Blocked,
+    /// The first line of the notes
+    Notes,
+}
+
+impl Column {
+    fn header(self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Status => "STATUS",
+            Column::Priority => "PRIORITY",
+            Column::Due => "DUE",
+            Column::Name => "NAME",
+            Column::Tags => "TAGS",
+            Column::Progress => "DONE",
+            Column::Repeats => "REPEATS",
+            Column::Blocked => "BLOCKED BY",
+            Column::Notes => "NOTES",
+        }
+    }
+
+    /// How narrow the column can be squeezed to fit the terminal, or `None` if it
+    /// never is. Columns are squeezed in the order `SQUEEZE_ORDER` lists them.
+    fn min_width(self) -> Option<usize> {
+        match self {
+            Column::Name => Some(12),
+            Column::Tags | Column::Repeats | Column::Notes | Column::Blocked => Some(6),
+            _ => None,
+        }
+    }
+}
+
+/// Every column but notes.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::Status,
+    Column::Priority,
+    Column::Due,
+    Column::Name,
+    Column::Tags,
+    Column::Progress,
+    Column::Repeats,
+    Column::Blocked,
+];
+
+const SQUEEZE_ORDER: &[Column] = &[Column::Notes, Column::Repeats, Column::Blocked, Column::Tags, Column::Name];
+
+const GAP: &str = "  ";
+
+pub struct TableOptions {
+    pub columns: Vec<Column>,
+    /// Leaves out columns that are empty for every todo shown.
+    pub hide_empty: bool,
+    /// The widest a line may be; `None` never truncates.
+    pub width: Option<usize>,
+    /// Spells out what color would otherwise show: a `[x]` or `[ ]` before each name,
+    /// and the status and priority after it when their columns aren't shown.
+    pub markers: bool,
+    pub theme: Theme,
+    /// strftime format for due dates.
+    pub date_format: String,
+    pub now: DateTime<Local>,
+}
+
+impl Default for TableOptions {
+    fn default() -> TableOptions {
+        TableOptions {
+            columns: DEFAULT_COLUMNS.to_vec(),
+            hide_empty: true,
+            width: terminal_width(),
+            markers: false,
+            theme: Theme::default(),
+            date_format: DUE_FORMAT.to_string(),
+            now: Local::now(),
+        }
+    }
+}
+
+struct Cell {
+    text: String,
+    color: Option<Color>,
+    bold: bool,
+}
+
+/// The width of the terminal stdout is going to, or `$COLUMNS` when it isn't one.
+pub fn terminal_width() -> Option<usize> {
+    match terminal_size::terminal_size() {
+        Some((terminal_size::Width(width), _)) => Some(width.into()),
+        None => env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()),
+    }
+}
+
+/// Draws `todos` as a table with a header row, subtasks indented under their
+/// parents. `all` is every todo in the list, for working out progress and blockers.
+pub fn render_table(todos: Vec<Todo>, all: &[Todo], options: &TableOptions) -> String {
+    let rollups = rollups(all);
+    let rows: Vec<Vec<Cell>> = tree(todos)
+        .into_iter()
+        .map(|(depth, todo)| {
+            let theme = &options.theme;
+            let row_color = if todo.completed { theme.done.color() } else { theme.priority(todo.priority) };
+            let blocked_by: Vec<String> = blockers(&todo, all).iter().map(|blocker| blocker.id.to_string()).collect();
+            let blocked_by = if todo.completed { Vec::new() } else { blocked_by };
+
+            options
+                .columns
+                .iter()
+                .map(|column| {
+                    let cell = |text: String| Cell { text, color: Some(row_color), bold: false };
+                    match column {
+                        Column::Id => cell(todo.id.to_string()),
+                        Column::Status => match status(&todo, &blocked_by, options.now) {
+                            "overdue" => Cell { text: "overdue".to_string(), color: Some(theme.overdue.color()), bold: true },
+                            "blocked" => Cell { text: "blocked".to_string(), color: Some(theme.blocked.color()), bold: false },
+                            status => cell(status.to_string()),
+                        },
+                        Column::Priority => cell(todo.priority.to_string()),
+                        Column::Due => cell(todo.due.map(|due| due.format(&options.date_format).to_string()).unwrap_or_default()),
+                        Column::Name => cell(name(&todo, depth, &blocked_by, options)),
+                        Column::Tags => cell(todo.tags.iter().map(|tag| format!("+{}", tag)).collect::<Vec<_>>().join(" ")),
+                        Column::Progress => cell(
+                            rollups
+                                .get(&todo.id)
+                                .map(|rollup| format!("{}/{}", rollup.done, rollup.total))
+                                .unwrap_or_default(),
+                        ),
+                        Column::Repeats => cell(todo.recurrence.as_ref().map(|recurrence| recurrence.describe()).unwrap_or_default()),
+                        Column::Blocked => Cell { text: blocked_by.join(", "), color: Some(theme.blocked.color()), bold: false },
+                        Column::Notes => cell(todo.notes.lines().next().unwrap_or_default().to_string()),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut shown: Vec<usize> = (0..options.columns.len())
+        .filter(|index| !options.hide_empty || rows.iter().any(|row| !row[*index].text.is_empty()))
+        .collect();
+    let mut columns: Vec<Column> = shown.iter().map(|index| options.columns[*index]).collect();
+    let mut natural: Vec<usize> = shown
+        .iter()
+        .map(|index| {
+            rows.iter()
+                .map(|row| display_width(&row[*index].text))
+                .chain([options.columns[*index].header().width()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut widths = natural.clone();
+    if let Some(width) = options.width {
+        squeeze(&columns, &mut widths, width);
+        // still too wide: leave out columns from the right, keeping the name
+        while total_width(&widths) > width && columns.len() > 1 {
+            let Some(index) = columns.iter().rposition(|column| *column != Column::Name) else {
+                break;
+            };
+            shown.remove(index);
+            columns.remove(index);
+            natural.remove(index);
+            widths = natural.clone();
+            squeeze(&columns, &mut widths, width);
+        }
+    }
+
+    let header: Vec<Cell> = columns
+        .iter()
+        .map(|column| Cell { text: column.header().to_string(), color: None, bold: true })
+        .collect();
+    let mut output = render_row(&header.iter().collect::<Vec<_>>(), &widths);
+    for row in &rows {
+        let cells: Vec<&Cell> = shown.iter().map(|index| &row[*index]).collect();
+        output.push_str(&render_row(&cells, &widths));
+    }
+
+    output
+}
+
+/// The todo's name, indented to its depth and marked `[notes]` if it has notes
+/// that aren't shown in a column of their own.
+fn name(todo: &Todo, depth: usize, blocked_by: &[String], options: &TableOptions) -> String {
+    let indent = "  ".repeat(depth);
+    let notes = if todo.notes.is_empty() || options.columns.contains(&Column::Notes) { "" } else { " [notes]" };
+    if !options.markers {
+        return format!("{}{}{}", indent, todo.name, notes);
+    }
+
+    let check = if todo.completed { "x" } else { " " };
+    let mut details = Vec::new();
+    if !options.columns.contains(&Column::Status) {
+        match status(todo, blocked_by, options.now) {
+            "overdue" => details.push("overdue".to_string()),
+            "blocked" => details.push("blocked".to_string()),
+            _ => {},
+        }
+    }
+    if !options.columns.contains(&Column::Priority) && todo.priority != Priority::Medium {
+        details.push(format!("{} priority", todo.priority));
+    }
+    let details = if details.is_empty() { String::new() } else { format!(" ({})", details.join(", ")) };
+
+    format!("{}[{}] {}{}{}", indent, check, todo.name, notes, details)
+}
+
+fn status(todo: &Todo, blocked_by: &[String], now: DateTime<Local>) -> &'static str {
+    if todo.completed {
+        "done"
+    } else if todo.is_overdue(now) {
+        "overdue"
+    } else if !blocked_by.is_empty() {
+        "blocked"
+    } else {
+        "open"
+    }
+}
+
+/// Narrows the squeezable columns, in `SQUEEZE_ORDER`, until the table fits in `width`
+/// or they can't go any narrower.
+fn squeeze(columns: &[Column], widths: &mut [usize], width: usize) {
+    for squeezable in SQUEEZE_ORDER {
+        let excess = total_width(widths).saturating_sub(width);
+        if excess == 0 {
+            return;
+        }
+        if let (Some(index), Some(min_width)) = (columns.iter().position(|column| column == squeezable), squeezable.min_width()) {
+            let min_width = min_width.max(squeezable.header().width());
+            widths[index] -= excess.min(widths[index].saturating_sub(min_width));
+        }
+    }
+}
+
+fn total_width(widths: &[usize]) -> usize {
+    widths.iter().sum::<usize>() + GAP.len() * widths.len().saturating_sub(1)
+}
+
+fn render_row(cells: &[&Cell], widths: &[usize]) -> String {
+    let last = cells.len().saturating_sub(1);
+    let mut line = String::new();
+    for (index, (cell, width)) in cells.iter().zip(widths).enumerate() {
+        let text = truncate(&cell.text, *width);
+        let padding = width.saturating_sub(display_width(&text));
+        line.push_str(&style(&text, cell).to_string());
+        if index != last {
+            line.push_str(&" ".repeat(padding));
+            line.push_str(GAP);
+        }
+    }
+
+    // empty cells at the end of a row would otherwise leave trailing spaces
+    line.trim_end().to_string() + "\n"
+}
+
+fn style(text: &str, cell: &Cell) -> ColoredString {
+    let styled = match cell.color {
+        Some(color) => text.color(color),
+        None => text.normal(),
+    };
+
+    if cell.bold { styled.bold() } else { styled }
+}
+
+/// How many columns `text` takes on screen, measured a character at a time the
+/// way `truncate` cuts it, so control characters count as nothing either way.
+fn display_width(text: &str) -> usize {
+    text.chars().map(|char| char.width().unwrap_or(0)).sum()
+}
+
+/// Cuts `text` down to `width` columns on screen, ending it with `…` if anything was cut.
+fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    let mut used = 0;
+    for char in text.chars() {
+        let char_width = char.width().unwrap_or(0);
+        if used + char_width + 1 > width {
+            break;
+        }
+        truncated.push(char);
+        used += char_width;
+    }
+    if width > 0 {
+        truncated.push('…');
+    }
+
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 5, 12, 0, 0).unwrap()
+    }
+
+    fn todos() -> Vec<Todo> {
+        vec![
+            Todo {
+                due: Some(Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap()),
+                priority: Priority::High,
+                tags: vec!["home".to_string()],
+                ..Todo::new(1, "Buy milk".to_string())
+            },
+            Todo { completed: true, parent_id: Some(1), ..Todo::new(2, "Find the café".to_string()) },
+            Todo { depends_on: vec![1], ..Todo::new(3, "Make 日本茶 latte".to_string()) },
+        ]
+    }
+
+    fn render(columns: &[Column], width: Option<usize>) -> String {
+        colored::control::set_override(false);
+        let options = TableOptions { columns: columns.to_vec(), hide_empty: true, width, now: now(), ..TableOptions::default() };
+
+        render_table(todos(), &todos(), &options)
+    }
+
+    #[test]
+    fn test_render_table_aligns_columns() {
+        // Act
+        let table = render(DEFAULT_COLUMNS, None);
+
+        // Assert
+        assert_eq!(table, [
+            "ID  STATUS   PRIORITY  DUE              NAME               TAGS   DONE  BLOCKED BY",
+            "1   open     high      Mon May 6 09:00  Buy milk           +home  1/1",
+            "2   done     medium                       Find the café",
+            "3   blocked  medium                     Make 日本茶 latte               1",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_render_table_truncates_to_width() {
+        // Act
+        let table = render(&[Column::Id, Column::Name, Column::Priority], Some(26));
+
+        // Assert
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "ID  NAME          PRIORITY");
+        assert_eq!(lines[3], "3   Make 日本茶…  medium");
+        assert!(lines.iter().all(|line| line.width() <= 26));
+    }
+
+    #[test]
+    fn test_render_table_with_control_characters_in_name() {
+        // Arrange
+        let todos = vec![Todo::new(1, "tab\there\t and a long name after it".to_string())];
+        let options = TableOptions { columns: vec![Column::Id, Column::Name, Column::Priority], width: Some(20), now: now(), ..TableOptions::default() };
+
+        // Act
+        let table = render_table(todos.clone(), &todos, &options);
+
+        // Assert
+        assert!(table.lines().all(|line| display_width(line) <= 20));
+    }
+
+    #[test]
+    fn test_render_table_leaves_out_columns_that_cant_fit() {
+        // Act
+        let table = render(&[Column::Id, Column::Name, Column::Priority, Column::Due], Some(20));
+
+        // Assert
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "ID  NAME");
+        assert_eq!(lines[1], "1   Buy milk");
+    }
+
+    #[test]
+    fn test_render_table_with_markers() {
+        // Arrange
+        colored::control::set_override(false);
+        let options = TableOptions { columns: vec![Column::Id, Column::Name], width: None, markers: true, now: now(), ..TableOptions::default() };
+
+        // Act
+        let table = render_table(todos(), &todos(), &options);
+
+        // Assert
+        assert_eq!(table, [
+            "ID  NAME",
+            "1   [ ] Buy milk (high priority)",
+            "2     [x] Find the café",
+            "3   [ ] Make 日本茶 latte (blocked)",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_render_table_marks_notes_unless_shown() {
+        // Arrange
+        colored::control::set_override(false);
+        let todos = vec![Todo { notes: "Semi-skimmed\nTwo pints".to_string(), ..Todo::new(1, "Buy milk".to_string()) }];
+        let without = TableOptions { columns: vec![Column::Id, Column::Name], now: now(), ..TableOptions::default() };
+        let with = TableOptions { columns: vec![Column::Id, Column::Name, Column::Notes], now: now(), ..TableOptions::default() };
+
+        // Act
+        let marked = render_table(todos.clone(), &todos, &without);
+        let shown = render_table(todos.clone(), &todos, &with);
+
+        // Assert
+        assert_eq!(marked.lines().nth(1), Some("1   Buy milk [notes]"));
+        assert_eq!(shown.lines().nth(1), Some("1   Buy milk  Semi-skimmed"));
+    }
+
+    #[test]
+    fn test_truncate_respects_wide_characters() {
+        assert_eq!(truncate("日本茶 latte", 6), "日本…");
+        assert_eq!(truncate("日本茶", 6), "日本茶");
+        assert_eq!(truncate("abc", 0), "");
+    }
+}
@@ -1,18 +1,74 @@
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::error::NudgelystError;
+use crate::lists::{TodoList, DEFAULT_LIST};
+use crate::recurrence::Recurrence;
+
+/// How `annotate` stamps the notes it adds.
+pub const NOTE_TIMESTAMP_FORMAT: &str = "[%Y-%m-%d %H:%M]";
 
 pub trait TodoRepository {
-    fn get_todo(&self, id: usize) -> Result<Todo, Box<dyn Error>>;
-    fn get_todo_by_name(&self, name: String) -> Result<Todo, Box<dyn Error>>;
-    fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>>;
-    fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>>;
-    fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>>;
-    fn create_todo(&self, todo: Todo) -> Result<(), Box<dyn Error>>;
-    fn create_todos(&self, todos: Vec<Todo>) -> Result<(), Box<dyn Error>>;
-    fn update_todo(&self, todo: Todo) -> Result<(), Box<dyn Error>>;
-    fn update_todos(&self, todos: Vec<Todo>) -> Result<(), Box<dyn Error>>;
-    fn delete_todo(&self, todo: Todo) -> Result<(), Box<dyn Error>>;
-    fn delete_todos(&self, todos: Vec<Todo>) -> Result<(), Box<dyn Error>>;
+    fn get_todo(&self, id: usize) -> Result<Todo, NudgelystError>;
+    fn get_todo_by_name(&self, name: String) -> Result<Todo, NudgelystError>;
+    fn get_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError>;
+    fn get_completed_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError>;
+    fn get_incomplete_todos(&self, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError>;
+    fn get_todos_with_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError>;
+    fn get_todos_without_tag(&self, tag: String, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError>;
+    /// How many todos carry each tag.
+    fn get_tag_counts(&self) -> Result<BTreeMap<String, usize>, NudgelystError>;
+    /// The todos in the list named `list`.
+    fn get_todos_in_list(&self, list: &str, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError>;
+    /// Every list, the default one first, with how many todos are in it.
+    fn get_lists(&self) -> Result<Vec<TodoList>, NudgelystError>;
+    /// Adds an empty list, failing with a conflict if one of that name exists.
+    fn create_list(&self, name: &str) -> Result<(), NudgelystError>;
+    /// Renames a list, its todos included.
+    fn rename_list(&self, from: &str, to: &str) -> Result<(), NudgelystError>;
+    /// Deletes a list along with every todo in it.
+    fn delete_list(&self, name: &str) -> Result<(), NudgelystError>;
+    /// Moves `todos` to the existing list named `to` in one go. Any whose parent
+    /// isn't among them stop being subtasks, as their parent stays behind.
+    fn move_todos(&self, todos: Vec<Todo>, to: &str) -> Result<(), NudgelystError>;
+    fn create_todo(&self, todo: Todo) -> Result<(), NudgelystError>;
+    fn create_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError>;
+    fn update_todo(&self, todo: Todo) -> Result<(), NudgelystError>;
+    fn update_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError>;
+    fn delete_todo(&self, todo: Todo) -> Result<(), NudgelystError>;
+    fn delete_todos(&self, todos: Vec<Todo>) -> Result<(), NudgelystError>;
+}
+
+/// How much a todo matters, lowest first so that higher priorities compare greater.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Low => write!(f, "low"),
+            Priority::Medium => write!(f, "medium"),
+            Priority::High => write!(f, "high"),
+        }
+    }
+}
+
+/// The moments in a todo's life that get recorded.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampKind {
+    #[default]
+    Created,
+    Updated,
+    Completed,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -20,6 +76,42 @@ pub struct Todo {
     pub id: usize,
     pub name: String,
     pub completed: bool,
+    /// Bumped by the repository on every update. An update or delete carrying
+    /// an older revision than the stored one is rejected as a conflict.
+    #[serde(default)]
+    pub revision: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<DateTime<Local>>,
+    /// Minutes before `due` to send a nudge, on top of the one sent when it falls due.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remind_before: Vec<u64>,
+    /// Makes completing the todo queue up its next occurrence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Lowercase, without the leading `+` used to add them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Free-form, possibly multi-line text; `annotate` adds timestamped lines.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// The todo this is a subtask of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<usize>,
+    /// Ids of the todos that have to be done before this one can be started.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<usize>,
+    /// Stamped by the repository. Todos from before these were recorded have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Local>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Local>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Local>>,
+    /// The list the todo is in; `None` for the default list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list: Option<String>,
 }
 
 impl Todo {
@@ -28,47 +120,246 @@ impl Todo {
             id,
             name,
             completed: false,
+            revision: 0,
+            due: None,
+            remind_before: Vec::new(),
+            recurrence: None,
+            priority: Priority::default(),
+            tags: Vec::new(),
+            notes: String::new(),
+            parent_id: None,
+            depends_on: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            completed_at: None,
+            list: None,
+        }
+    }
+
+    /// The name of the list the todo is in.
+    pub fn list_name(&self) -> &str {
+        self.list.as_deref().unwrap_or(DEFAULT_LIST)
+    }
+
+    /// When the given thing happened to the todo, if it's known.
+    pub fn timestamp(&self, kind: TimestampKind) -> Option<DateTime<Local>> {
+        match kind {
+            TimestampKind::Created => self.created_at,
+            TimestampKind::Updated => self.updated_at,
+            TimestampKind::Completed => self.completed_at,
+        }
+    }
+
+    /// Fills in the timestamps a repository records when it stores a new todo at `now`,
+    /// keeping any the todo already has, e.g. when importing a list.
+    pub(crate) fn stamp_created(&mut self, now: DateTime<Local>) {
+        let created_at = *self.created_at.get_or_insert(now);
+        self.updated_at.get_or_insert(created_at);
+        self.completed_at = if self.completed { self.completed_at.or(Some(now)) } else { None };
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(&normalize_tag(tag))
+    }
+
+    /// Adds `note` to the end of the notes on a line of its own, stamped with `now`.
+    pub fn annotate(&mut self, note: &str, now: DateTime<Local>) {
+        if !self.notes.is_empty() && !self.notes.ends_with('\n') {
+            self.notes.push('\n');
         }
+        self.notes.push_str(&format!("{} {}", now.format(NOTE_TIMESTAMP_FORMAT), note.trim()));
     }
+
+    /// Whether the todo is still open past its due date.
+    pub fn is_overdue(&self, now: DateTime<Local>) -> bool {
+        !self.completed && self.due.is_some_and(|due| due < now)
+    }
+}
+
+/// Marks `todo` as done. A recurring todo is kept as a record of the completion
+/// and a fresh copy is created for its next occurrence, which is returned.
+pub fn complete_todo(repo: &dyn TodoRepository, todo: Todo, now: DateTime<Local>) -> Result<Option<Todo>, NudgelystError> {
+    let next = match &todo.recurrence {
+        Some(recurrence) => Some(Todo {
+            id: 0,
+            completed: false,
+            revision: 0,
+            due: Some(recurrence.next_due(todo.due.unwrap_or(now), now)?),
+            // keeps later occurrences on the day of the month this one fell on
+            recurrence: Some(recurrence.anchored(todo.due.unwrap_or(now).date_naive())),
+            created_at: None,
+            updated_at: None,
+            completed_at: None,
+            ..todo.clone()
+        }),
+        None => None,
+    };
+
+    repo.update_todo(Todo { completed: true, recurrence: None, ..todo })?;
+    if let Some(next) = &next {
+        repo.create_todo(next.clone())?;
+    }
+
+    Ok(next)
+}
+
+pub fn get_todos(repo: &dyn TodoRepository, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
+    repo.get_todos(limit)
+}
+
+/// Tags are matched case-insensitively, with or without their leading `+`.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim_start_matches('+').to_lowercase()
 }
 
-// pub fn get_todo(repo: &dyn TodoRepository, id: usize) -> Result<Todo, Box<dyn Error>> {
+/// Names are shown one to a line in a table, so they can't hold tabs, line breaks
+/// or other control characters.
+pub fn check_name(name: &str) -> Result<(), NudgelystError> {
+    if name.chars().any(char::is_control) {
+        return Err(NudgelystError::InvalidInput(format!(
+            "the name {:?} has tabs, line breaks or other control characters in it",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Splits quick-add text like `fix CI +work +urgent` into the todo's name and its tags.
+pub fn split_tags(text: &str) -> (String, Vec<String>) {
+    let (tags, words): (Vec<&str>, Vec<&str>) = text
+        .split_whitespace()
+        .partition(|word| word.len() > 1 && word.starts_with('+'));
+
+    let mut unique_tags: Vec<String> = Vec::new();
+    for tag in tags.into_iter().map(normalize_tag) {
+        if !unique_tags.contains(&tag) {
+            unique_tags.push(tag);
+        }
+    }
+
+    (words.join(" "), unique_tags)
+}
+
+// pub fn get_todo(repo: &dyn TodoRepository, id: usize) -> Result<Todo, NudgelystError> {
 //     repo.get_todo(id)
 // }
 //
-// pub fn get_todo_by_name(repo: &dyn TodoRepository, name: String) -> Result<Todo, Box<dyn Error>> {
+// pub fn get_todo_by_name(repo: &dyn TodoRepository, name: String) -> Result<Todo, NudgelystError> {
 //     repo.get_todo_by_name(name)
 // }
 
-pub fn get_todos(repo: &dyn TodoRepository, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
-    repo.get_todos(limit)
-}
-
-// pub fn get_completed_todos(repo: &dyn TodoRepository, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
+// pub fn get_completed_todos(repo: &dyn TodoRepository, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
 //     repo.get_completed_todos(limit)
 // }
-// fn get_incomplete_todos(repo: &dyn TodoRepository, limit: Option<usize>) -> Result<Vec<Todo>, Box<dyn Error>> {
+// fn get_incomplete_todos(repo: &dyn TodoRepository, limit: Option<usize>) -> Result<Vec<Todo>, NudgelystError> {
 //     repo.get_incomplete_todos(limit)
 // }
 
-// pub fn create_todo(repo: &dyn TodoRepository, todo: Todo) -> Result<(), Box<dyn Error>> {
+// pub fn create_todo(repo: &dyn TodoRepository, todo: Todo) -> Result<(), NudgelystError> {
 //     repo.create_todo(todo)
 // }
 
-// pub fn create_todos(repo: &dyn TodoRepository, todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
+// pub fn create_todos(repo: &dyn TodoRepository, todos: Vec<Todo>) -> Result<(), NudgelystError> {
 //     repo.create_todos(todos)
 // }
 
-// pub fn update_todo(repo: &dyn TodoRepository, todo: Todo) -> Result<(), Box<dyn Error>> {
+// pub fn update_todo(repo: &dyn TodoRepository, todo: Todo) -> Result<(), NudgelystError> {
 //     repo.update_todo(todo)
 // }
-// pub fn update_todos(repo: &dyn TodoRepository, todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
+// pub fn update_todos(repo: &dyn TodoRepository, todos: Vec<Todo>) -> Result<(), NudgelystError> {
 //     repo.update_todos(todos)
 // }
-// pub fn delete_todo(repo: &dyn TodoRepository, todo: Todo) -> Result<(), Box<dyn Error>> {
+// pub fn delete_todo(repo: &dyn TodoRepository, todo: Todo) -> Result<(), NudgelystError> {
 //     repo.delete_todo(todo)
 // }
-// pub fn delete_todos(repo: &dyn TodoRepository, todos: Vec<Todo>) -> Result<(), Box<dyn Error>> {
+// pub fn delete_todos(repo: &dyn TodoRepository, todos: Vec<Todo>) -> Result<(), NudgelystError> {
 //     repo.delete_todos(todos)
 // }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::in_memory_todo_repository::InMemoryTodoRepository;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_split_tags() {
+        // Act
+        let (name, tags) = split_tags("fix CI +work  +Urgent +work c++ +");
+
+        // Assert
+        assert_eq!(name, "fix CI c++ +");
+        assert_eq!(tags, vec!["work", "urgent"]);
+    }
+
+    #[test]
+    fn test_annotate() {
+        // Arrange
+        let mut todo = Todo { notes: "Ask about the oat kind".to_string(), ..Todo::new(1, "Buy milk".to_string()) };
+
+        // Act
+        todo.annotate(" Shop was out of it ", at(3, 18));
+
+        // Assert
+        assert_eq!(todo.notes, "Ask about the oat kind\n[2024-05-03 18:00] Shop was out of it");
+    }
+
+    #[test]
+    fn test_complete_todo() {
+        // Arrange
+        let repo = InMemoryTodoRepository::with_todos(vec![Todo::new(0, "Task 1".to_string())]).unwrap();
+
+        // Act
+        let result = complete_todo(&repo, repo.get_todo(1).unwrap(), at(1, 10));
+
+        // Assert
+        match result {
+            Ok(next) => {
+                assert_eq!(next, None);
+                assert!(repo.get_todo(1).unwrap().completed);
+                assert_eq!(repo.get_todos(None).unwrap().len(), 1);
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_complete_recurring_todo_creates_next_occurrence() {
+        // Arrange
+        let recurring = Todo {
+            due: Some(at(6, 9)),
+            remind_before: vec![30],
+            recurrence: Some("mon".parse().unwrap()),
+            ..Todo::new(0, "Rotate on-call notes".to_string())
+        };
+        let repo = InMemoryTodoRepository::with_todos(vec![recurring]).unwrap();
+
+        // Act
+        let result = complete_todo(&repo, repo.get_todo(1).unwrap(), at(6, 10));
+
+        // Assert
+        match result {
+            Ok(next) => {
+                assert_eq!(next.unwrap().due, Some(at(13, 9)));
+                let done = repo.get_todo(1).unwrap();
+                assert!(done.completed);
+                assert_eq!(done.recurrence, None);
+                let next = repo.get_todo(2).unwrap();
+                assert!(!next.completed);
+                assert_eq!(next.due, Some(at(13, 9)));
+                assert_eq!(next.remind_before, vec![30]);
+                assert_eq!(next.recurrence.unwrap().to_string(), "FREQ=WEEKLY;BYDAY=MO");
+            }
+            Err(err) => {
+                panic!("Unexpected error: {:?}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use crate::error::NudgelystError;
+use crate::lists::{count_lists, list_field, validate_list_name, TodoList, DEFAULT_LIST};
+use crate::todo::Todo;
+
+/// A whole todo list along with the highest id ever handed out in it.
+///
+/// Ids are allocated from `last_id` rather than from the todos currently in the
+/// list, so an id is never reused for the lifetime of the list - even after the
+/// todo holding it is deleted.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct TodoStore {
+    pub last_id: usize,
+    pub todos: Vec<Todo>,
+    /// Named lists in the order they were created, empty ones included.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lists: Vec<String>,
+}
+
+/// The on-disk shapes a store can take. Lists written before ids were tracked
+/// are a bare sequence of todos.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum StoredTodos {
+    Store(TodoStore),
+    Legacy(Vec<Todo>),
+}
+
+impl From<StoredTodos> for TodoStore {
+    fn from(stored: StoredTodos) -> TodoStore {
+        match stored {
+            StoredTodos::Store(store) => store,
+            StoredTodos::Legacy(todos) => TodoStore::from_todos(todos),
+        }
+    }
+}
+
+impl TodoStore {
+    pub fn from_todos(todos: Vec<Todo>) -> TodoStore {
+        let last_id = todos.iter().map(|todo| todo.id).max().unwrap_or(0);
+
+        TodoStore { last_id, todos, lists: Vec::new() }
+    }
+
+    /// Adds a todo to the store. A todo with an id of 0 is given the next free id;
+    /// an explicit id is kept as long as no other todo is using it.
+    /// Returns the id the todo was stored with.
+    pub fn insert(&mut self, mut todo: Todo) -> Result<usize, NudgelystError> {
+        todo.stamp_created(Local::now());
+        if todo.id == 0 {
+            self.last_id += 1;
+            todo.id = self.last_id;
+        } else if self.todos.iter().any(|existing| existing.id == todo.id) {
+            return Err(NudgelystError::Conflict(format!("Todo with id '{}' already exists", todo.id)));
+        } else {
+            self.last_id = self.last_id.max(todo.id);
+        }
+
+        let id = todo.id;
+        self.todos.push(todo);
+
+        Ok(id)
+    }
+
+    /// Replaces the stored todo sharing `todo`'s id, bumps its revision and stamps
+    /// when it was updated and, if it has just been done, completed.
+    pub fn update(&mut self, mut todo: Todo) -> Result<(), NudgelystError> {
+        let index = self.current_index(&todo)?;
+        let stored = &self.todos[index];
+        let now = Local::now();
+        todo.revision += 1;
+        todo.created_at = stored.created_at;
+        todo.updated_at = Some(now);
+        todo.completed_at = if todo.completed { todo.completed_at.or(stored.completed_at).or(Some(now)) } else { None };
+        self.todos[index] = todo;
+
+        Ok(())
+    }
+
+    /// Removes the stored todo sharing `todo`'s id, along with any dependencies on it.
+    pub fn remove(&mut self, todo: &Todo) -> Result<(), NudgelystError> {
+        let index = self.current_index(todo)?;
+        self.todos.remove(index);
+
+        // left at the same revision so the rest of a batch of deletes still applies
+        for dependent in &mut self.todos {
+            dependent.depends_on.retain(|id| *id != todo.id);
+        }
+
+        Ok(())
+    }
+
+    pub fn lists(&self) -> Vec<TodoList> {
+        count_lists(&self.lists, &self.todos)
+    }
+
+    pub fn create_list(&mut self, name: &str) -> Result<(), NudgelystError> {
+        validate_list_name(name)?;
+        if self.has_list(name) {
+            return Err(NudgelystError::Conflict(format!("there's already a list named `{}`", name)));
+        }
+
+        self.lists.push(name.to_string());
+
+        Ok(())
+    }
+
+    /// Renames the list and moves its todos over, bumping their revisions.
+    pub fn rename_list(&mut self, from: &str, to: &str) -> Result<(), NudgelystError> {
+        self.check_named_list(from)?;
+        validate_list_name(to)?;
+        if self.has_list(to) {
+            return Err(NudgelystError::Conflict(format!("there's already a list named `{}`", to)));
+        }
+
+        match self.lists.iter_mut().find(|list| *list == from) {
+            Some(list) => *list = to.to_string(),
+            None => self.lists.push(to.to_string()),
+        }
+        for todo in self.todos.iter_mut().filter(|todo| todo.list_name() == from) {
+            todo.list = list_field(to);
+            todo.revision += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the list with its todos, along with any dependencies on them.
+    pub fn delete_list(&mut self, name: &str) -> Result<(), NudgelystError> {
+        self.check_named_list(name)?;
+
+        let removed: Vec<usize> = self.todos.iter().filter(|todo| todo.list_name() == name).map(|todo| todo.id).collect();
+        self.todos.retain(|todo| todo.list_name() != name);
+        for dependent in &mut self.todos {
+            dependent.depends_on.retain(|id| !removed.contains(id));
+        }
+        self.lists.retain(|list| list != name);
+
+        Ok(())
+    }
+
+    /// Moves `todos` to the list named `to`, as updates to them. Any whose parent
+    /// isn't moving too stop being subtasks.
+    pub fn move_todos(&mut self, todos: &[Todo], to: &str) -> Result<(), NudgelystError> {
+        if !self.has_list(to) {
+            return Err(NudgelystError::no_list(to));
+        }
+
+        let moving: HashSet<usize> = todos.iter().map(|todo| todo.id).collect();
+        let now = Local::now();
+        for todo in todos {
+            let index = self.current_index(todo)?;
+            let stored = &mut self.todos[index];
+            stored.list = list_field(to);
+            stored.parent_id = stored.parent_id.filter(|parent_id| moving.contains(parent_id));
+            stored.revision += 1;
+            stored.updated_at = Some(now);
+        }
+
+        Ok(())
+    }
+
+    fn has_list(&self, name: &str) -> bool {
+        self.lists().iter().any(|list| list.name == name)
+    }
+
+    /// Makes sure `name` is a list that can be renamed or deleted.
+    fn check_named_list(&self, name: &str) -> Result<(), NudgelystError> {
+        if name == DEFAULT_LIST {
+            return Err(NudgelystError::InvalidInput(format!("the `{}` list can't be renamed or deleted", DEFAULT_LIST)));
+        }
+        if !self.has_list(name) {
+            return Err(NudgelystError::no_list(name));
+        }
+
+        Ok(())
+    }
+
+    pub fn tag_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for tag in self.todos.iter().flat_map(|todo| &todo.tags) {
+            *counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Finds where `todo` is stored, making sure it was read from the latest revision.
+    fn current_index(&self, todo: &Todo) -> Result<usize, NudgelystError> {
+        let index = self
+            .todos
+            .iter()
+            .position(|existing| existing.id == todo.id)
+            .ok_or_else(|| NudgelystError::not_found_id(todo.id))?;
+
+        let stored_revision = self.todos[index].revision;
+        if stored_revision != todo.revision {
+            return Err(NudgelystError::stale_revision(todo.id, stored_revision, todo.revision));
+        }
+
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_allocates_sequential_ids() {
+        // Arrange
+        let mut store = TodoStore::default();
+
+        // Act
+        let first = store.insert(Todo::new(0, "Task 1".to_string())).unwrap();
+        let second = store.insert(Todo::new(0, "Task 2".to_string())).unwrap();
+
+        // Assert
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(store.last_id, 2);
+    }
+
+    #[test]
+    fn test_insert_does_not_reuse_deleted_ids() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.insert(Todo::new(0, "Task 1".to_string())).unwrap();
+        store.insert(Todo::new(0, "Task 2".to_string())).unwrap();
+        store.todos.retain(|todo| todo.id != 2);
+
+        // Act
+        let result = store.insert(Todo::new(0, "Task 3".to_string()));
+
+        // Assert
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_insert_explicit_id_raises_last_id() {
+        // Arrange
+        let mut store = TodoStore::default();
+
+        // Act
+        store.insert(Todo::new(7, "Task 7".to_string())).unwrap();
+        let result = store.insert(Todo::new(0, "Task 8".to_string()));
+
+        // Assert
+        assert_eq!(result.unwrap(), 8);
+    }
+
+    #[test]
+    fn test_insert_duplicate_id() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.insert(Todo::new(1, "Task 1".to_string())).unwrap();
+
+        // Act
+        let result = store.insert(Todo::new(1, "Task 1 again".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(store.todos.len(), 1);
+    }
+
+    #[test]
+    fn test_update_bumps_revision() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.insert(Todo::new(0, "Task 1".to_string())).unwrap();
+
+        // Act
+        let result = store.update(Todo { completed: true, ..store.todos[0].clone() });
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(store.todos[0].revision, 1);
+        assert!(store.todos[0].completed);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        // Arrange
+        let mut store = TodoStore::default();
+        let before = Local::now();
+        store.insert(Todo::new(0, "Task 1".to_string())).unwrap();
+        let created = store.todos[0].clone();
+
+        // Act
+        store.update(Todo { completed: true, created_at: None, ..created.clone() }).unwrap();
+        let completed = store.todos[0].clone();
+        store.update(Todo { completed: false, ..completed.clone() }).unwrap();
+
+        // Assert
+        let created_at = created.created_at.unwrap();
+        assert!(before <= created_at && created_at <= Local::now());
+        assert_eq!(created.updated_at, created.created_at);
+        assert_eq!(created.completed_at, None);
+        assert_eq!(completed.created_at, created.created_at);
+        assert!(completed.updated_at.unwrap() >= created_at);
+        assert!(completed.completed_at.is_some());
+        assert_eq!(store.todos[0].completed_at, None);
+    }
+
+    #[test]
+    fn test_update_stale_revision() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.insert(Todo::new(0, "Task 1".to_string())).unwrap();
+        let stale = store.todos[0].clone();
+        store.update(Todo { name: "Renamed".to_string(), ..stale.clone() }).unwrap();
+
+        // Act
+        let result = store.update(Todo { completed: true, ..stale });
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::Conflict(_))));
+        assert_eq!(store.todos[0].name, "Renamed");
+        assert!(!store.todos[0].completed);
+    }
+
+    #[test]
+    fn test_remove_drops_dependencies_on_the_todo() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.insert(Todo::new(0, "Task 1".to_string())).unwrap();
+        store.insert(Todo { depends_on: vec![1], ..Todo::new(0, "Task 2".to_string()) }).unwrap();
+
+        // Act
+        let result = store.remove(&store.todos[0].clone());
+
+        // Assert
+        assert!(result.is_ok());
+        assert!(store.todos[0].depends_on.is_empty());
+    }
+
+    #[test]
+    fn test_update_missing_todo() {
+        // Arrange
+        let mut store = TodoStore::default();
+
+        // Act
+        let result = store.update(Todo::new(1, "Task 1".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_remove_missing_todo() {
+        // Arrange
+        let mut store = TodoStore::default();
+
+        // Act
+        let result = store.remove(&Todo::new(1, "Task 1".to_string()));
+
+        // Assert
+        assert!(matches!(result, Err(NudgelystError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_rename_list_moves_its_todos() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.create_list("work").unwrap();
+        store.insert(Todo { list: list_field("work"), ..Todo::new(0, "Task 1".to_string()) }).unwrap();
+
+        // Act
+        let result = store.rename_list("work", "job");
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(store.lists, vec!["job".to_string()]);
+        assert_eq!(store.todos[0].list_name(), "job");
+        assert_eq!(store.todos[0].revision, 1);
+    }
+
+    #[test]
+    fn test_delete_list_deletes_its_todos() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.create_list("work").unwrap();
+        store.insert(Todo { list: list_field("work"), ..Todo::new(0, "Task 1".to_string()) }).unwrap();
+        store.insert(Todo { depends_on: vec![1], ..Todo::new(0, "Task 2".to_string()) }).unwrap();
+
+        // Act
+        let result = store.delete_list("work");
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(store.todos.len(), 1);
+        assert!(store.todos[0].depends_on.is_empty());
+        assert_eq!(store.lists().len(), 1);
+    }
+
+    #[test]
+    fn test_default_list_is_kept() {
+        // Arrange
+        let mut store = TodoStore::default();
+
+        // Act
+        let renamed = store.rename_list(DEFAULT_LIST, "inbox");
+        let deleted = store.delete_list(DEFAULT_LIST);
+        let created = store.create_list(DEFAULT_LIST);
+
+        // Assert
+        assert!(matches!(renamed, Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(deleted, Err(NudgelystError::InvalidInput(_))));
+        assert!(matches!(created, Err(NudgelystError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_move_todos() {
+        // Arrange
+        let mut store = TodoStore::default();
+        store.create_list("work").unwrap();
+        store.insert(Todo::new(0, "Task 1".to_string())).unwrap();
+        store.insert(Todo { parent_id: Some(1), ..Todo::new(0, "Task 2".to_string()) }).unwrap();
+        store.insert(Todo { parent_id: Some(1), ..Todo::new(0, "Task 3".to_string()) }).unwrap();
+        let todos = store.todos.clone();
+
+        // Act
+        let missing = store.move_todos(&todos[..1], "home");
+        let moved = store.move_todos(&todos[..2], "work");
+        let stale = store.move_todos(&todos[..1], DEFAULT_LIST);
+        let alone = store.move_todos(&todos[2..], "work");
+
+        // Assert
+        assert!(matches!(missing, Err(NudgelystError::InvalidInput(_))));
+        assert!(moved.is_ok());
+        assert!(matches!(stale, Err(NudgelystError::Conflict(_))));
+        assert_eq!(store.todos[0].list.as_deref(), Some("work"));
+        assert_eq!(store.todos[1].list.as_deref(), Some("work"));
+        assert_eq!(store.todos[1].parent_id, Some(1));
+        assert!(alone.is_ok());
+        assert_eq!(store.todos[2].parent_id, None);
+    }
+
+    #[test]
+    fn test_legacy_list_uses_highest_id() {
+        // Arrange
+        let content = "- id: 1\n  name: Task 1\n  completed: false\n- id: 4\n  name: Task 4\n  completed: true\n";
+
+        // Act
+        let stored: StoredTodos = serde_yaml::from_str(content).unwrap();
+        let store = TodoStore::from(stored);
+
+        // Assert
+        assert_eq!(store.last_id, 4);
+        assert_eq!(store.todos.len(), 2);
+    }
+}
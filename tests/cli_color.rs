@@ -0,0 +1,45 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Runs nudgelyst with its stdout piped, as when redirected to a file.
+fn nudgelyst(file: &Path, args: &[&str], no_color: Option<&str>) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_nudgelyst"));
+    command.arg("--file").arg(file).args(args).env_remove("NO_COLOR");
+    if let Some(no_color) = no_color {
+        command.env("NO_COLOR", no_color);
+    }
+
+    String::from_utf8(command.output().expect("failed to run nudgelyst").stdout).unwrap()
+}
+
+#[test]
+fn test_piped_output_is_uncolored() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    nudgelyst(&file, &["todo", "Task 1"], None);
+
+    // Act
+    let output = nudgelyst(&file, &["list"], None);
+
+    // Assert
+    assert!(output.contains("Task 1"));
+    assert!(!output.contains('\u{1b}'));
+}
+
+#[test]
+fn test_color_flag_overrides_detection() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    nudgelyst(&file, &["todo", "Task 1"], None);
+
+    // Act
+    let always = nudgelyst(&file, &["list", "--color", "always"], Some("1"));
+    let plain = nudgelyst(&file, &["--plain", "list", "--color", "always"], None);
+
+    // Assert
+    assert!(always.contains('\u{1b}'));
+    assert!(!plain.contains('\u{1b}'));
+    assert!(plain.contains("[ ] Task 1"));
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+use std::process::Command;
+
+fn nudgelyst(file: &Path, args: &[&str]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_nudgelyst"))
+        .arg("--file")
+        .arg(file)
+        .args(args)
+        .output()
+        .expect("failed to run nudgelyst")
+        .status
+        .code()
+}
+
+#[test]
+fn test_success_exits_zero() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+
+    // Act
+    let code = nudgelyst(&file, &["todo", "Task 1"]);
+
+    // Assert
+    assert_eq!(code, Some(0));
+    assert_eq!(nudgelyst(&file, &["todone", "--id", "1"]), Some(0));
+}
+
+#[test]
+fn test_not_found_exits_three() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+
+    // Act
+    let by_id = nudgelyst(&file, &["todone", "--id", "42"]);
+    let by_name = nudgelyst(&file, &["togone", "--name", "missing"]);
+
+    // Assert
+    assert_eq!(by_id, Some(3));
+    assert_eq!(by_name, Some(3));
+}
+
+#[test]
+fn test_selector_required_exactly_once() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    nudgelyst(&file, &["todo", "Task 1"]);
+
+    // Act
+    let neither = nudgelyst(&file, &["todone"]);
+    let both = nudgelyst(&file, &["tundo", "--id", "1", "--name", "Task 1"]);
+
+    // Assert
+    assert_eq!(neither, Some(2));
+    assert_eq!(both, Some(2));
+}
+
+#[test]
+fn test_init_existing_list_exits_four() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    nudgelyst(&file, &["init"]);
+
+    // Act
+    let code = nudgelyst(&file, &["init"]);
+
+    // Assert
+    assert_eq!(code, Some(4));
+}
+
+#[test]
+fn test_unparseable_list_exits_five() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    std::fs::write(&file, "- id: [not a number\n").unwrap();
+
+    // Act
+    let code = nudgelyst(&file, &["list"]);
+
+    // Assert
+    assert_eq!(code, Some(5));
+}
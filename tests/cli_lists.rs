@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn nudgelyst(file: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nudgelyst"))
+        .arg("--file")
+        .arg(file)
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("failed to run nudgelyst")
+}
+
+fn stdout(output: Output) -> String {
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_todos_stay_in_their_list() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    nudgelyst(&file, &["todo", "Buy milk"]);
+    nudgelyst(&file, &["list", "create", "work"]);
+
+    // Act
+    nudgelyst(&file, &["--list", "work", "todo", "Ship it"]);
+
+    // Assert
+    let default = stdout(nudgelyst(&file, &["list"]));
+    let work = stdout(nudgelyst(&file, &["list", "--list", "work"]));
+    assert!(default.contains("Buy milk") && !default.contains("Ship it"));
+    assert!(work.contains("Ship it") && !work.contains("Buy milk"));
+    assert_eq!(stdout(nudgelyst(&file, &["lists"])), "default: 1 todos, 0 done\nwork: 1 todos, 0 done\n");
+}
+
+#[test]
+fn test_move_and_delete_list() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    nudgelyst(&file, &["todo", "Ship it"]);
+    nudgelyst(&file, &["list", "create", "work"]);
+
+    // Act
+    let moved = nudgelyst(&file, &["move", "--id", "1", "--to", "work"]);
+    let refused = nudgelyst(&file, &["list", "delete", "work"]);
+    let missing = nudgelyst(&file, &["--list", "home", "list"]);
+
+    // Assert
+    assert!(moved.status.success());
+    assert_eq!(refused.status.code(), Some(4));
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(nudgelyst(&file, &["list", "delete", "work", "--force"]).status.success());
+    assert_eq!(stdout(nudgelyst(&file, &["lists"])), "default: 0 todos, 0 done\n");
+}
+
+#[test]
+fn test_todo_lists_the_newest_todos() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    for name in ["Buy milk", "Call mum", "Pay rent", "Water plants", "Book dentist"] {
+        nudgelyst(&file, &["todo", name]);
+    }
+
+    // Act
+    let output = stdout(nudgelyst(&file, &["todo", "Ship it"]));
+
+    // Assert
+    assert!(!output.contains("Buy milk"));
+    assert!(output.contains("Call mum") && output.contains("Ship it"));
+}
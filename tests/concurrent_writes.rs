@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use nudgelyst::file_todo_repository::FileTodoRepository;
+use nudgelyst::todo::TodoRepository;
+
+const PROCESSES: usize = 8;
+const TODOS_PER_PROCESS: usize = 10;
+
+fn nudgelyst(file: &Path, args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_nudgelyst"))
+        .arg("--file")
+        .arg(file)
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .expect("failed to run nudgelyst")
+        .success()
+}
+
+#[test]
+fn test_concurrent_processes_do_not_lose_todos() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+
+    // Act
+    let workers: Vec<_> = (0..PROCESSES)
+        .map(|process| {
+            let file = file.clone();
+            thread::spawn(move || {
+                for todo in 0..TODOS_PER_PROCESS {
+                    assert!(nudgelyst(&file, &["todo", &format!("process {} todo {}", process, todo)]));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    // Assert
+    let todos = FileTodoRepository::new(&file).get_todos(None).unwrap();
+    let names: HashSet<String> = todos.iter().map(|todo| todo.name.clone()).collect();
+    let ids: HashSet<usize> = todos.iter().map(|todo| todo.id).collect();
+    assert_eq!(todos.len(), PROCESSES * TODOS_PER_PROCESS);
+    assert_eq!(names.len(), PROCESSES * TODOS_PER_PROCESS);
+    assert_eq!(ids.len(), PROCESSES * TODOS_PER_PROCESS);
+}
+
+#[test]
+fn test_concurrent_completions_are_all_kept() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nudgelyst.yaml");
+    for todo in 0..PROCESSES * TODOS_PER_PROCESS {
+        assert!(nudgelyst(&file, &["todo", &format!("todo {}", todo)]));
+    }
+
+    // Act
+    let workers: Vec<_> = (0..PROCESSES)
+        .map(|process| {
+            let file = file.clone();
+            thread::spawn(move || {
+                for todo in 0..TODOS_PER_PROCESS {
+                    let id = process * TODOS_PER_PROCESS + todo + 1;
+                    assert!(nudgelyst(&file, &["todone", "--id", &id.to_string()]));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    // Assert
+    let todos = FileTodoRepository::new(&file).get_todos(None).unwrap();
+    assert_eq!(todos.len(), PROCESSES * TODOS_PER_PROCESS);
+    assert!(todos.iter().all(|todo| todo.completed));
+}